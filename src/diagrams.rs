@@ -0,0 +1,174 @@
+//! Mermaid diagrams for a [`StructuredPlan`]: a `gantt` chart of the tasks
+//! per milestone and a `flowchart` of their dependencies, so the timeline can
+//! be reviewed at a glance instead of read out of prose.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::structured_plan::{EstimateUnit, StructuredPlan, Task};
+
+/// Both diagrams as a markdown section, to append to the written plan.
+pub fn markdown(plan: &StructuredPlan) -> String {
+    format!(
+        "\n\n## Timeline\n\n```mermaid\n{}```\n\n## Task dependencies\n\n```mermaid\n{}```\n",
+        gantt(plan, &today()),
+        flowchart(plan)
+    )
+}
+
+/// Tasks grouped by milestone, starting on `start` (`YYYY-MM-DD`) or after
+/// the tasks they depend on, each milestone marked once its tasks are done.
+pub fn gantt(plan: &StructuredPlan, start: &str) -> String {
+    let mut chart = format!("gantt\n    title {}\n    dateFormat YYYY-MM-DD\n", label(&plan.title));
+
+    for milestone in &plan.milestones {
+        chart.push_str(&format!("    section {}\n", label(&format!("{}: {}", milestone.id, milestone.title))));
+
+        let tasks: Vec<&Task> = plan.tasks.iter().filter(|t| t.milestone == milestone.id).collect();
+        for task in &tasks {
+            let dependencies: Vec<String> = task
+                .depends_on
+                .iter()
+                .filter(|d| *d != &task.id)
+                .map(|d| node_id(d))
+                .collect();
+            let begins = if dependencies.is_empty() {
+                start.to_string()
+            } else {
+                format!("after {}", dependencies.join(" "))
+            };
+            chart.push_str(&format!(
+                "    {} :{}, {begins}, {}\n",
+                label(&task.title),
+                node_id(&task.id),
+                duration(task)
+            ));
+        }
+
+        if !tasks.is_empty() {
+            let after: Vec<String> = tasks.iter().map(|t| node_id(&t.id)).collect();
+            chart.push_str(&format!(
+                "    {} :milestone, {}, after {}, 0d\n",
+                label(&milestone.title),
+                node_id(&milestone.id),
+                after.join(" ")
+            ));
+        }
+    }
+    chart
+}
+
+/// One box per task inside a box per milestone, with an arrow from each
+/// dependency to the task waiting on it.
+pub fn flowchart(plan: &StructuredPlan) -> String {
+    let mut chart = String::from("flowchart LR\n");
+
+    for milestone in &plan.milestones {
+        chart.push_str(&format!(
+            "    subgraph {}[\"{}\"]\n",
+            node_id(&milestone.id),
+            quoted(&format!("{}: {}", milestone.id, milestone.title))
+        ));
+        for task in plan.tasks.iter().filter(|t| t.milestone == milestone.id) {
+            chart.push_str(&format!("        {}[\"{}\"]\n", node_id(&task.id), quoted(&format!("{}: {}", task.id, task.title))));
+        }
+        chart.push_str("    end\n");
+    }
+
+    for task in &plan.tasks {
+        for dependency in task.depends_on.iter().filter(|d| *d != &task.id) {
+            chart.push_str(&format!("    {} --> {}\n", node_id(dependency), node_id(&task.id)));
+        }
+    }
+    chart
+}
+
+/// Mermaid ids are bare words; plan ids like `T1` already are, but models
+/// sometimes write `T-1` or `task 1`.
+fn node_id(id: &str) -> String {
+    id.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+/// Gantt lines split on `:` and end at `#` or `;`.
+fn label(text: &str) -> String {
+    text.replace(':', " -").replace(['#', ';'], "").replace('\n', " ")
+}
+
+fn quoted(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', " ")
+}
+
+/// Whole numbers keep their unit; fractions become hours, which Mermaid
+/// can only take as integers.
+fn duration(task: &Task) -> String {
+    let (value, unit, hours_per_unit) = match task.estimate.unit {
+        EstimateUnit::Hours => (task.estimate.value, "h", 1.0),
+        EstimateUnit::Days => (task.estimate.value, "d", 24.0),
+        EstimateUnit::Weeks => (task.estimate.value, "w", 168.0),
+    };
+    if value.fract() == 0.0 {
+        format!("{value}{unit}")
+    } else {
+        format!("{}h", (value * hours_per_unit).round().max(1.0))
+    }
+}
+
+/// Today's UTC date as `YYYY-MM-DD`.
+fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 86_400);
+    date_from_days(days as i64)
+}
+
+/// Howard Hinnant's `civil_from_days`: days since 1970-01-01 to a date.
+fn date_from_days(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan() -> StructuredPlan {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/structured_plan.json");
+        StructuredPlan::parse(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn gantt_schedules_tasks_after_their_dependencies() {
+        let mut plan = plan();
+        plan.tasks[1].estimate.value = 1.5;
+        let chart = gantt(&plan, "2026-01-05");
+
+        assert!(chart.starts_with("gantt\n    title Nightly product scraper\n    dateFormat YYYY-MM-DD\n"));
+        assert!(chart.contains("    section M1 - Scraper prototype\n"));
+        assert!(chart.contains("    Fetch and parse product pages :T1, 2026-01-05, 3d\n"));
+        assert!(chart.contains("    Design the products schema :T2, 2026-01-05, 36h\n"));
+        assert!(chart.contains("    Bulk load with sqlx COPY :T3, after T1 T2, 2d\n"));
+        assert!(chart.contains("    Nightly load in production :milestone, M2, after T3, 0d\n"));
+    }
+
+    #[test]
+    fn flowchart_groups_by_milestone_and_links_dependencies() {
+        let mut plan = plan();
+        plan.tasks[0].title = "Fetch \"product\" pages".to_string();
+        let chart = flowchart(&plan);
+
+        assert!(chart.contains("    subgraph M1[\"M1: Scraper prototype\"]\n        T1[\"T1: Fetch #quot;product#quot; pages\"]\n"));
+        assert!(chart.contains("    T1 --> T3\n    T2 --> T3\n"));
+    }
+
+    #[test]
+    fn days_since_epoch_become_dates() {
+        assert_eq!(date_from_days(0), "1970-01-01");
+        assert_eq!(date_from_days(19_782), "2024-02-29");
+        assert_eq!(date_from_days(20_454), "2026-01-01");
+    }
+}
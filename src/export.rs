@@ -0,0 +1,325 @@
+//! Turn a [`StructuredPlan`] into issues on GitHub or GitLab: one milestone
+//! per plan milestone, one issue per task, with dependencies linked.
+//!
+//! The export is a request script: the REST calls in creation order. Numbers
+//! the tracker hands out (milestone and issue numbers) aren't known until the
+//! earlier requests have run, so later requests refer to them with
+//! `{{milestone:M1}}` / `{{task:T1}}` placeholders that are filled in as the
+//! export runs. `--dry-run` writes the script to disk instead of running it;
+//! it is for review, not something either tracker's import can read.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use rocket::serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::llm::Error;
+use crate::structured_plan::{EstimateUnit, StructuredPlan, Task};
+
+const GITHUB_API_URL: &str = "https://api.github.com";
+const GITLAB_URL: &str = "https://gitlab.com";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum Tracker {
+    Github,
+    Gitlab,
+}
+
+/// One call against the tracker's REST API.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiRequest {
+    pub method: &'static str,
+    /// Relative to the API root, e.g. `/repos/acme/shop/issues`.
+    pub path: String,
+    pub body: Value,
+    /// The placeholder this request defines, e.g. `task:T1`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creates: Option<String>,
+    /// The response field holding the number for `creates`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_field: Option<&'static str>,
+}
+
+impl ApiRequest {
+    fn post(path: String, body: Value) -> Self {
+        Self { method: "POST", path, body, creates: None, id_field: None }
+    }
+
+    fn creating(mut self, key: &str, id_field: &'static str) -> Self {
+        self.creates = Some(key.to_string());
+        self.id_field = Some(id_field);
+        self
+    }
+}
+
+/// The requests that recreate `plan` in `project` (`owner/repo` on GitHub,
+/// the numeric id or `group/project` path on GitLab), in the order they
+/// have to run.
+pub fn requests(plan: &StructuredPlan, tracker: Tracker, project: &str) -> Vec<ApiRequest> {
+    let mut requests = Vec::new();
+    let project_path = match tracker {
+        Tracker::Github => format!("/repos/{project}"),
+        Tracker::Gitlab => format!("/projects/{}", project.replace('/', "%2F")),
+    };
+
+    for milestone in &plan.milestones {
+        let mut description = format!("Target: {}", milestone.target);
+        if let Some(text) = &milestone.description {
+            description = format!("{text}\n\n{description}");
+        }
+        let body = json!({ "title": format!("{}: {}", milestone.id, milestone.title), "description": description });
+        let id_field = match tracker {
+            Tracker::Github => "number",
+            Tracker::Gitlab => "id",
+        };
+        requests.push(
+            ApiRequest::post(format!("{project_path}/milestones"), body).creating(&milestone_key(&milestone.id), id_field),
+        );
+    }
+
+    for task in in_dependency_order(&plan.tasks) {
+        let title = format!("{}: {}", task.id, task.title);
+        let body = issue_body(plan, task);
+        let labels = labels(task);
+        let request = match tracker {
+            Tracker::Github => ApiRequest::post(
+                format!("{project_path}/issues"),
+                json!({
+                    "title": title,
+                    "body": body,
+                    "labels": labels,
+                    "milestone": placeholder(&milestone_key(&task.milestone)),
+                }),
+            )
+            .creating(&task_key(&task.id), "number"),
+            Tracker::Gitlab => ApiRequest::post(
+                format!("{project_path}/issues"),
+                json!({
+                    "title": title,
+                    "description": body,
+                    "labels": labels.join(","),
+                    "milestone_id": placeholder(&milestone_key(&task.milestone)),
+                }),
+            )
+            .creating(&task_key(&task.id), "iid"),
+        };
+        requests.push(request);
+
+        // GitHub links `#n` mentions by itself; GitLab gets explicit links
+        if tracker == Tracker::Gitlab {
+            for dependency in &task.depends_on {
+                requests.push(ApiRequest::post(
+                    format!("{project_path}/issues/{}/links", placeholder(&task_key(&task.id))),
+                    json!({
+                        "target_project_id": project,
+                        "target_issue_iid": placeholder(&task_key(dependency)),
+                        "link_type": "relates_to",
+                    }),
+                ));
+            }
+        }
+    }
+    requests
+}
+
+fn placeholder(key: &str) -> String {
+    format!("{{{{{key}}}}}")
+}
+
+/// Milestones and tasks are numbered separately by the tracker, and a plan
+/// may give a milestone and a task the same id, so their keys are kept apart.
+fn milestone_key(id: &str) -> String {
+    format!("milestone:{id}")
+}
+
+fn task_key(id: &str) -> String {
+    format!("task:{id}")
+}
+
+fn issue_body(plan: &StructuredPlan, task: &Task) -> String {
+    let mut body = format!("{}\n\n**Estimate:** {}", task.description, estimate(task));
+    if !task.depends_on.is_empty() {
+        let blockers: Vec<String> = task.depends_on.iter().map(|d| format!("#{}", placeholder(&task_key(d)))).collect();
+        body.push_str(&format!("\n**Blocked by:** {}", blockers.join(", ")));
+    }
+    body.push_str(&format!("\n\n_Generated from the plan \"{}\"._", plan.title));
+    body
+}
+
+fn estimate(task: &Task) -> String {
+    let unit = match task.estimate.unit {
+        EstimateUnit::Hours => "hours",
+        EstimateUnit::Days => "days",
+        EstimateUnit::Weeks => "weeks",
+    };
+    format!("{} {unit}", task.estimate.value)
+}
+
+fn labels(task: &Task) -> Vec<String> {
+    vec!["plan".to_string(), format!("estimate: {}", estimate(task))]
+}
+
+/// Tasks with their dependencies first, so every `{{task:…}}` a request refers
+/// to has already been created. The plan was validated to be acyclic.
+fn in_dependency_order(tasks: &[Task]) -> Vec<&Task> {
+    let mut ordered: Vec<&Task> = Vec::new();
+    while ordered.len() < tasks.len() {
+        let before = ordered.len();
+        for task in tasks {
+            let placed = |id: &String| ordered.iter().any(|t| &t.id == id);
+            if !placed(&task.id) && task.depends_on.iter().all(|d| d == &task.id || placed(d)) {
+                ordered.push(task);
+            }
+        }
+        if ordered.len() == before {
+            break; // a cycle after all; leave the rest out rather than spin
+        }
+    }
+    ordered
+}
+
+/// Replace placeholders with the numbers the tracker returned. A string that
+/// is only a placeholder becomes a JSON number (`"milestone": 3`).
+fn fill(value: &Value, created: &HashMap<String, u64>) -> Value {
+    match value {
+        Value::String(text) => {
+            for (key, number) in created {
+                if *text == placeholder(key) {
+                    return json!(number);
+                }
+            }
+            let mut text = text.clone();
+            for (key, number) in created {
+                text = text.replace(&placeholder(key), &number.to_string());
+            }
+            Value::String(text)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|v| fill(v, created)).collect()),
+        Value::Object(fields) => Value::Object(fields.iter().map(|(k, v)| (k.clone(), fill(v, created))).collect()),
+        other => other.clone(),
+    }
+}
+
+/// `--dry-run`: write the request script to `<dir>/<tracker>-requests.json`.
+pub fn write_dry_run(requests: &[ApiRequest], tracker: Tracker, dir: &Path) -> Result<std::path::PathBuf, Error> {
+    std::fs::create_dir_all(dir)?;
+    let name = match tracker {
+        Tracker::Github => "github-requests.json",
+        Tracker::Gitlab => "gitlab-requests.json",
+    };
+    let path = dir.join(name);
+    std::fs::write(&path, serde_json::to_string_pretty(requests)?)?;
+    Ok(path)
+}
+
+/// Send the requests in order. Tokens come from `GITHUB_TOKEN` /
+/// `GITLAB_TOKEN`; `GITHUB_API_URL` and `GITLAB_URL` point at self-hosted
+/// instances.
+pub async fn send(requests: &[ApiRequest], tracker: Tracker) -> Result<HashMap<String, u64>, Error> {
+    let (root, token_var) = match tracker {
+        Tracker::Github => (std::env::var("GITHUB_API_URL").unwrap_or_else(|_| GITHUB_API_URL.to_string()), "GITHUB_TOKEN"),
+        Tracker::Gitlab => {
+            let base = std::env::var("GITLAB_URL").unwrap_or_else(|_| GITLAB_URL.to_string());
+            (format!("{}/api/v4", base.trim_end_matches('/')), "GITLAB_TOKEN")
+        }
+    };
+    let token = std::env::var(token_var).map_err(|_| format!("{token_var} must be set in environment"))?;
+    let client = reqwest::Client::new();
+
+    let mut created = HashMap::new();
+    for request in requests {
+        let url = fill(&Value::String(format!("{}{}", root.trim_end_matches('/'), request.path)), &created);
+        let url = url.as_str().unwrap_or_default().to_string();
+        let builder = client.post(&url).json(&fill(&request.body, &created));
+        let builder = match tracker {
+            Tracker::Github => builder
+                .bearer_auth(&token)
+                .header("Accept", "application/vnd.github+json")
+                .header("User-Agent", "mls-gigachad"),
+            Tracker::Gitlab => builder.header("PRIVATE-TOKEN", &token),
+        };
+
+        let response = builder.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(format!("{} {url} failed with {status}: {error_text}", request.method).into());
+        }
+        let reply: Value = response.json().await?;
+        if let (Some(key), Some(field)) = (&request.creates, request.id_field) {
+            let number = reply[field].as_u64().ok_or_else(|| format!("{url}: no `{field}` in the response"))?;
+            println!("Created {key} as #{number}");
+            created.insert(key.clone(), number);
+        }
+    }
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan() -> StructuredPlan {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/structured_plan.json");
+        StructuredPlan::parse(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn github_export_creates_milestones_then_issues_in_dependency_order() {
+        let mut plan = plan();
+        plan.tasks.reverse(); // T3 first: it must still come after T1 and T2
+        let requests = requests(&plan, Tracker::Github, "acme/shop");
+
+        let created: Vec<_> = requests.iter().map(|r| r.creates.as_deref().unwrap()).collect();
+        assert_eq!(created, ["milestone:M1", "milestone:M2", "task:T2", "task:T1", "task:T3"]);
+        assert!(requests.iter().all(|r| r.path.starts_with("/repos/acme/shop/")));
+
+        let t3 = &requests[4].body;
+        assert_eq!(t3["title"], "T3: Bulk load with sqlx COPY");
+        assert_eq!(t3["milestone"], "{{milestone:M2}}");
+        assert_eq!(t3["labels"], json!(["plan", "estimate: 2 days"]));
+        assert!(t3["body"].as_str().unwrap().contains("**Blocked by:** #{{task:T1}}, #{{task:T2}}"), "{t3}");
+    }
+
+    #[test]
+    fn gitlab_export_links_dependencies() {
+        let requests = requests(&plan(), Tracker::Gitlab, "acme/shop");
+        let links: Vec<_> = requests.iter().filter(|r| r.path.ends_with("/links")).collect();
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].path, "/projects/acme%2Fshop/issues/{{task:T3}}/links");
+        assert_eq!(links[0].body["target_issue_iid"], "{{task:T1}}");
+        let issue = requests.iter().find(|r| r.creates.as_deref() == Some("task:T1")).unwrap();
+        assert_eq!(issue.body["labels"], "plan,estimate: 3 days");
+        assert_eq!(issue.id_field, Some("iid"));
+    }
+
+    #[test]
+    fn placeholders_are_filled_with_created_numbers() {
+        let created = HashMap::from([("milestone:M2".to_string(), 7), ("task:T1".to_string(), 41)]);
+        let body = json!({ "milestone": "{{milestone:M2}}", "body": "Blocked by: #{{task:T1}}", "labels": ["plan"] });
+        assert_eq!(
+            fill(&body, &created),
+            json!({ "milestone": 7, "body": "Blocked by: #41", "labels": ["plan"] })
+        );
+    }
+
+    #[test]
+    fn a_milestone_and_a_task_may_share_an_id() {
+        let mut plan = plan();
+        plan.milestones[0].id = "T1".to_string();
+        for task in plan.tasks.iter_mut().filter(|t| t.milestone == "M1") {
+            task.milestone = "T1".to_string();
+        }
+        let requests = requests(&plan, Tracker::Github, "acme/shop");
+        let created = HashMap::from([("milestone:T1".to_string(), 3), ("task:T1".to_string(), 41)]);
+
+        let t1 = requests.iter().find(|r| r.creates.as_deref() == Some("task:T1")).unwrap();
+        assert_eq!(fill(&t1.body, &created)["milestone"], 3);
+        let t3 = requests.iter().find(|r| r.creates.as_deref() == Some("task:T3")).unwrap();
+        assert!(fill(&t3.body, &created)["body"].as_str().unwrap().contains("#41"));
+    }
+}
@@ -0,0 +1,410 @@
+//! Background plan jobs: `create_plan` runs in its own tokio task and clients
+//! poll for progress instead of holding a request open for the whole run.
+//! Every job is mirrored to a [`JobStore`] so a restart can resume it.
+
+mod store;
+
+pub use store::JobStore;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rocket::serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::task::AbortHandle;
+
+use crate::llm::{Error, Message, SharedLlm};
+use crate::planner::{self, PlanEvent, PlanFormat, PlanStage, ResearchPhase, ResearchState, SearchFn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Done,
+    /// The run ended in an error; see `error`.
+    Failed,
+    Cancelled,
+}
+
+/// What `GET /planner/jobs/<id>` reports.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct JobSnapshot {
+    pub id: String,
+    pub status: JobStatus,
+    pub stage: Option<PlanStage>,
+    /// The research phase, once research has started.
+    pub phase: Option<ResearchPhase>,
+    pub search_count: usize,
+    /// Search headers and notes gathered so far, as the researcher sees them.
+    pub knowledge_base: String,
+    /// The reply once the job is done: the questions or the final plan.
+    pub plan: Option<String>,
+    /// What went wrong, once the job has failed.
+    pub error: Option<String>,
+}
+
+struct Job {
+    snapshot: JobSnapshot,
+    task: Option<AbortHandle>,
+}
+
+/// One write to the [`JobStore`], run by the writer task.
+type Write = Box<dyn FnOnce(&JobStore) -> Result<(), Error> + Send>;
+
+pub struct Jobs {
+    llm: SharedLlm,
+    search: Arc<SearchFn>,
+    jobs: Mutex<HashMap<String, Job>>,
+    store: Arc<JobStore>,
+    /// Writes waiting for the store, oldest first.
+    writes: UnboundedSender<Write>,
+}
+
+impl Jobs {
+    /// Writes to `store` go to one task on the blocking pool, in the order
+    /// they were queued, so SQLite never holds up the runtime or the `jobs`
+    /// lock that `GET` and `DELETE` wait on.
+    pub fn new(llm: SharedLlm, search: Arc<SearchFn>, store: JobStore) -> Arc<Self> {
+        let store = Arc::new(store);
+        let (writes, mut queue) = unbounded_channel::<Write>();
+        let writer = Arc::clone(&store);
+        tokio::task::spawn_blocking(move || {
+            while let Some(write) = queue.blocking_recv() {
+                persist(write(&writer));
+            }
+        });
+
+        Arc::new(Self {
+            llm,
+            search,
+            jobs: Mutex::new(HashMap::new()),
+            store,
+            writes,
+        })
+    }
+
+    /// Queue a write. Callers queue while they still hold the `jobs` lock, so
+    /// the store sees the snapshots in the order they changed.
+    fn save(&self, write: impl FnOnce(&JobStore) -> Result<(), Error> + Send + 'static) {
+        let _ = self.writes.send(Box::new(write));
+    }
+
+    /// Wait for every write queued so far.
+    #[cfg(test)]
+    async fn flushed(&self) {
+        let (done, flushed) = tokio::sync::oneshot::channel();
+        self.save(move |_| {
+            let _ = done.send(());
+            Ok(())
+        });
+        flushed.await.ok();
+    }
+
+    /// Load every stored job and restart the ones that were still running
+    /// from their last checkpoint. Returns how many were restarted.
+    pub fn resume(self: &Arc<Self>) -> Result<usize, Error> {
+        let mut resumed = 0;
+        for stored in self.store.load()? {
+            let mut snapshot = stored.snapshot;
+            if let Some(checkpoint) = &stored.checkpoint {
+                snapshot.phase = Some(checkpoint.phase.clone());
+                snapshot.search_count = checkpoint.search_count;
+                snapshot.knowledge_base = checkpoint.knowledge_base.clone();
+            }
+            let id = snapshot.id.clone();
+            let running = snapshot.status == JobStatus::Running;
+            self.jobs.lock().unwrap().insert(id.clone(), Job { snapshot, task: None });
+
+            if running {
+                self.spawn(id, stored.conversation, stored.format, stored.checkpoint);
+                resumed += 1;
+            }
+        }
+        Ok(resumed)
+    }
+
+    /// Start planning `conversation` in the background and return the job id.
+    pub fn start(self: &Arc<Self>, conversation: Vec<Message>, format: PlanFormat) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        let snapshot = JobSnapshot {
+            id: id.clone(),
+            status: JobStatus::Running,
+            stage: None,
+            phase: None,
+            search_count: 0,
+            knowledge_base: String::new(),
+            plan: None,
+            error: None,
+        };
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            let (stored, stored_conversation) = (snapshot.clone(), conversation.clone());
+            self.save(move |store| store.insert(&stored, &stored_conversation, format));
+            jobs.insert(id.clone(), Job { snapshot, task: None });
+        }
+
+        self.spawn(id.clone(), conversation, format, None);
+        id
+    }
+
+    fn spawn(
+        self: &Arc<Self>,
+        id: String,
+        conversation: Vec<Message>,
+        format: PlanFormat,
+        resume: Option<ResearchState>,
+    ) {
+        let jobs = Arc::clone(self);
+        let job_id = id.clone();
+        let task = tokio::spawn(async move {
+            let on_event = |event: PlanEvent| jobs.record(&job_id, event);
+            let on_checkpoint = |checkpoint: &ResearchState| jobs.checkpoint(&job_id, checkpoint);
+            let plan = planner::run_plan(
+                jobs.llm.as_ref(),
+                &conversation,
+                format,
+                resume,
+                jobs.search.as_ref(),
+                &on_event,
+                &on_checkpoint,
+            )
+            .await;
+            jobs.finish(&job_id, plan);
+        });
+
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            job.task = Some(task.abort_handle());
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<JobSnapshot> {
+        self.jobs.lock().unwrap().get(id).map(|job| job.snapshot.clone())
+    }
+
+    /// Stop a running job. Finished jobs are left as they are.
+    pub fn cancel(&self, id: &str) -> Option<JobSnapshot> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(id)?;
+        if job.snapshot.status == JobStatus::Running {
+            if let Some(task) = job.task.take() {
+                task.abort();
+            }
+            job.snapshot.status = JobStatus::Cancelled;
+            let snapshot = job.snapshot.clone();
+            self.save(move |store| store.save(&snapshot));
+        }
+        Some(job.snapshot.clone())
+    }
+
+    fn record(&self, id: &str, event: PlanEvent) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(job) = jobs.get_mut(id) else {
+            return;
+        };
+        let snapshot = &mut job.snapshot;
+        match event {
+            PlanEvent::Stage { stage } => {
+                snapshot.stage = Some(stage);
+                let snapshot = snapshot.clone();
+                self.save(move |store| store.save(&snapshot));
+            }
+            PlanEvent::Search { number, query } => {
+                snapshot.search_count = number;
+                snapshot
+                    .knowledge_base
+                    .push_str(&format!("\n--- Search #{number}: {query} ---\n"));
+            }
+            PlanEvent::Phase { phase } => snapshot.phase = Some(phase),
+            PlanEvent::Synthesis { text } => snapshot.knowledge_base.push_str(&text),
+            PlanEvent::Components { .. }
+            | PlanEvent::PageFetched { .. }
+            | PlanEvent::PageFailed { .. }
+            | PlanEvent::PageSkipped { .. }
+            | PlanEvent::Compacted { .. }
+            | PlanEvent::Token { .. } => {}
+        }
+    }
+
+    /// Events keep the snapshot current between turns; a checkpoint replaces
+    /// it with the exact research state, source lists included.
+    fn checkpoint(&self, id: &str, checkpoint: &ResearchState) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(id) {
+            job.snapshot.search_count = checkpoint.search_count;
+            job.snapshot.knowledge_base = checkpoint.knowledge_base.clone();
+        }
+        // Serialized by the writer, not here
+        let (id, checkpoint) = (id.to_string(), checkpoint.clone());
+        self.save(move |store| store.save_checkpoint(&id, &checkpoint));
+    }
+
+    fn finish(&self, id: &str, plan: Result<String, Error>) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id)
+            && job.snapshot.status == JobStatus::Running
+        {
+            match plan {
+                Ok(plan) => {
+                    job.snapshot.status = JobStatus::Done;
+                    job.snapshot.plan = Some(plan);
+                }
+                Err(e) => {
+                    job.snapshot.status = JobStatus::Failed;
+                    job.snapshot.error = Some(e.to_string());
+                }
+            }
+            job.task = None;
+            let snapshot = job.snapshot.clone();
+            self.save(move |store| store.save(&snapshot));
+        }
+    }
+}
+
+/// A failed write only costs resumability, so it shouldn't stop the job.
+fn persist(result: Result<(), Error>) {
+    if let Err(e) = result {
+        println!("Failed to save plan job: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{LlmProvider, MockLlm};
+    use crate::planner::{OnEvent, Page};
+    use futures::future::BoxFuture;
+
+    fn canned_search<'a>(query: &'a str, _on_event: OnEvent<'a>) -> BoxFuture<'a, Vec<Page>> {
+        Box::pin(async move { vec![Page { url: "https://example.com".to_string(), title: None, content: format!("about {query}") }] })
+    }
+
+    fn never_search<'a>(_query: &'a str, _on_event: OnEvent<'a>) -> BoxFuture<'a, Vec<Page>> {
+        Box::pin(futures::future::pending())
+    }
+
+    /// Hangs on the third search of the fixture, the way a request in flight
+    /// does when the server goes down.
+    fn stalls_on_javascript<'a>(query: &'a str, on_event: OnEvent<'a>) -> BoxFuture<'a, Vec<Page>> {
+        if query.contains("javascript") {
+            never_search(query, on_event)
+        } else {
+            canned_search(query, on_event)
+        }
+    }
+
+    fn research_conversation() -> Vec<Message> {
+        vec![
+            Message::new("user", "Build a Rust web scraper for product data"),
+            Message::new("assistant", "Which sites?"),
+            Message::new("user", "Three static shops."),
+        ]
+    }
+
+    /// The pipeline fixture with its first `skip` turns already used up. The
+    /// question turn is always skipped: these jobs start straight at research.
+    async fn mock(skip: usize) -> Arc<MockLlm> {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/plan_pipeline.json");
+        let llm = MockLlm::from_file(fixture).unwrap();
+        for _ in 0..skip {
+            llm.complete(vec![], None).await.unwrap();
+        }
+        Arc::new(llm)
+    }
+
+    fn memory_store() -> JobStore {
+        JobStore::open(":memory:").unwrap()
+    }
+
+    async fn wait_until_finished(jobs: &Jobs, id: &str) -> JobSnapshot {
+        loop {
+            let snapshot = jobs.get(id).unwrap();
+            if snapshot.status != JobStatus::Running {
+                return snapshot;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn job_runs_in_background_until_done() {
+        let jobs = Jobs::new(mock(1).await, Arc::new(canned_search), memory_store());
+        let id = jobs.start(research_conversation(), PlanFormat::Markdown);
+
+        let snapshot = wait_until_finished(&jobs, &id).await;
+        assert_eq!(snapshot.status, JobStatus::Done);
+        assert_eq!(snapshot.stage, Some(PlanStage::Plan));
+        assert_eq!(snapshot.phase, Some(ResearchPhase::Synthesis));
+        assert_eq!(snapshot.search_count, 4);
+        assert!(snapshot.knowledge_base.contains("--- Search #3: scraping javascript heavy shops rust ---"));
+        assert!(snapshot.plan.unwrap().starts_with("## Final Technical Plan"));
+    }
+
+    #[tokio::test]
+    async fn a_run_that_errors_ends_failed() {
+        let llm = mock(1).await;
+        while llm.complete(vec![], None).await.is_ok() {}
+        let jobs = Jobs::new(llm, Arc::new(canned_search), memory_store());
+        let id = jobs.start(research_conversation(), PlanFormat::Markdown);
+
+        let snapshot = wait_until_finished(&jobs, &id).await;
+        assert_eq!(snapshot.status, JobStatus::Failed);
+        assert_eq!(snapshot.plan, None);
+        assert!(snapshot.error.unwrap().starts_with("mock error: mock fixture exhausted"));
+        jobs.flushed().await;
+        let stored = jobs.store.load().unwrap().remove(0).snapshot;
+        assert_eq!((stored.status, stored.error.is_some()), (JobStatus::Failed, true));
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_a_running_job() {
+        let jobs = Jobs::new(mock(1).await, Arc::new(never_search), memory_store());
+        let id = jobs.start(research_conversation(), PlanFormat::Markdown);
+
+        while jobs.get(&id).unwrap().search_count == 0 {
+            tokio::task::yield_now().await;
+        }
+        let snapshot = jobs.cancel(&id).unwrap();
+        assert_eq!(snapshot.status, JobStatus::Cancelled);
+        assert_eq!(snapshot.plan, None);
+        assert!(jobs.get("no-such-job").is_none());
+    }
+
+    #[tokio::test]
+    async fn restart_resumes_from_last_checkpoint() {
+        let path = std::env::temp_dir().join(format!("mls-gigachad-jobs-{}.db", uuid::Uuid::new_v4()));
+
+        // Search #1 finishes, then the server "dies" during search #3
+        let jobs = Jobs::new(mock(1).await, Arc::new(stalls_on_javascript), JobStore::open(&path).unwrap());
+        let id = jobs.start(research_conversation(), PlanFormat::Markdown);
+        while jobs.get(&id).unwrap().search_count < 3 {
+            tokio::task::yield_now().await;
+        }
+        jobs.jobs.lock().unwrap()[&id].task.as_ref().unwrap().abort();
+        jobs.flushed().await;
+        drop(jobs);
+
+        // After the restart the model is asked to start on the first component
+        let llm = mock(4).await;
+        let jobs = Jobs::new(llm.clone(), Arc::new(canned_search), JobStore::open(&path).unwrap());
+        assert_eq!(jobs.resume().unwrap(), 1);
+
+        let restored = jobs.get(&id).unwrap();
+        assert_eq!(restored.status, JobStatus::Running);
+        assert_eq!(restored.phase, Some(ResearchPhase::ComponentAnalysis("Fetching and parsing".to_string())));
+        assert_eq!(restored.search_count, 1);
+        assert!(!restored.knowledge_base.contains("Search #2"));
+
+        let snapshot = wait_until_finished(&jobs, &id).await;
+        assert_eq!(snapshot.status, JobStatus::Done);
+        assert_eq!(snapshot.search_count, 4);
+        assert!(snapshot.plan.unwrap().starts_with("## Final Technical Plan"));
+
+        // Research picked up at the component brief rather than starting over
+        let first_request = &llm.requests()[4];
+        let brief = first_request.last().unwrap().content.as_deref().unwrap();
+        assert!(brief.starts_with("Phase: component analysis of **Fetching and parsing**"), "{brief}");
+        assert!(first_request.iter().any(|m| m.tool_call_id.as_deref() == Some("call_1")));
+
+        std::fs::remove_file(&path).ok();
+    }
+}
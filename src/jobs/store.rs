@@ -0,0 +1,179 @@
+//! SQLite persistence for plan jobs, so restarting the server resumes research
+//! instead of throwing away the searches (and API spend) already made.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use rocket::serde::de::DeserializeOwned;
+use rocket::serde::Serialize;
+use rusqlite::{params, Connection};
+use serde_json::Value;
+
+use super::JobSnapshot;
+use crate::llm::{Error, Message};
+use crate::planner::{PlanFormat, ResearchState};
+
+const DEFAULT_JOBS_DB: &str = "jobs.db";
+
+/// Schema changes in order; `PRAGMA user_version` records how many have run.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS jobs (
+        id             TEXT PRIMARY KEY,
+        status         TEXT NOT NULL,
+        stage          TEXT,
+        search_count   INTEGER NOT NULL,
+        knowledge_base TEXT NOT NULL,
+        plan           TEXT,
+        conversation   TEXT NOT NULL,
+        checkpoint     TEXT
+    )",
+    "ALTER TABLE jobs ADD COLUMN format TEXT NOT NULL DEFAULT 'markdown'",
+    "ALTER TABLE jobs ADD COLUMN error TEXT",
+];
+
+/// A job as it was last written: enough to report it and, if it was still
+/// running, to pick its research back up.
+pub struct StoredJob {
+    pub snapshot: JobSnapshot,
+    pub conversation: Vec<Message>,
+    pub format: PlanFormat,
+    pub checkpoint: Option<ResearchState>,
+}
+
+pub struct JobStore {
+    conn: Mutex<Connection>,
+}
+
+impl JobStore {
+    /// Open (or create) the database at `path`; `:memory:` keeps it in RAM.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut conn = Connection::open(path)
+            .map_err(|e| format!("cannot open job database {}: {e}", path.display()))?;
+        migrate(&mut conn)?;
+
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// `JOBS_DB` names the database file, `jobs.db` by default.
+    pub fn from_env() -> Result<Self, Error> {
+        Self::open(std::env::var("JOBS_DB").unwrap_or_else(|_| DEFAULT_JOBS_DB.to_string()))
+    }
+
+    pub fn insert(&self, snapshot: &JobSnapshot, conversation: &[Message], format: PlanFormat) -> Result<(), Error> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO jobs (id, status, stage, search_count, knowledge_base, plan, conversation, format, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                snapshot.id,
+                to_text(&snapshot.status),
+                snapshot.stage.as_ref().map(to_text),
+                snapshot.search_count,
+                snapshot.knowledge_base,
+                snapshot.plan,
+                serde_json::to_string(conversation)?,
+                to_text(&format),
+                snapshot.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Write everything `GET /planner/jobs/<id>` reports.
+    pub fn save(&self, snapshot: &JobSnapshot) -> Result<(), Error> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE jobs SET status = ?2, stage = ?3, search_count = ?4, knowledge_base = ?5, plan = ?6, error = ?7
+             WHERE id = ?1",
+            params![
+                snapshot.id,
+                to_text(&snapshot.status),
+                snapshot.stage.as_ref().map(to_text),
+                snapshot.search_count,
+                snapshot.knowledge_base,
+                snapshot.plan,
+                snapshot.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Record the research state after a completed turn.
+    pub fn save_checkpoint(&self, id: &str, checkpoint: &ResearchState) -> Result<(), Error> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE jobs SET search_count = ?2, knowledge_base = ?3, checkpoint = ?4 WHERE id = ?1",
+            params![
+                id,
+                checkpoint.search_count,
+                checkpoint.knowledge_base,
+                serde_json::to_string(checkpoint)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Every job, oldest first.
+    pub fn load(&self) -> Result<Vec<StoredJob>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT id, status, stage, search_count, knowledge_base, plan, conversation, checkpoint, format, error
+             FROM jobs ORDER BY rowid",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, usize>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, Option<String>>(7)?,
+                row.get::<_, String>(8)?,
+                row.get::<_, Option<String>>(9)?,
+            ))
+        })?;
+
+        rows.map(|row| {
+            let (id, status, stage, search_count, knowledge_base, plan, conversation, checkpoint, format, error) = row?;
+            Ok(StoredJob {
+                snapshot: JobSnapshot {
+                    id,
+                    status: from_text(status)?,
+                    stage: stage.map(from_text).transpose()?,
+                    phase: None,
+                    search_count,
+                    knowledge_base,
+                    plan,
+                    error,
+                },
+                conversation: serde_json::from_str(&conversation)?,
+                format: from_text(format)?,
+                checkpoint: checkpoint.map(|c| serde_json::from_str(&c)).transpose()?,
+            })
+        })
+        .collect()
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), Error> {
+    let applied: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let tx = conn.transaction()?;
+    for migration in MIGRATIONS.iter().skip(applied) {
+        tx.execute_batch(migration)?;
+    }
+    tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Status, stage and format are stored as their JSON names, e.g. `running`.
+fn to_text<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(text)) => text,
+        _ => String::new(),
+    }
+}
+
+fn from_text<T: DeserializeOwned>(text: String) -> Result<T, Error> {
+    Ok(serde_json::from_value(Value::String(text))?)
+}
@@ -0,0 +1,235 @@
+//! LLM provider abstraction.
+//!
+//! `chat` and `create_plan` only ever talk to an [`LlmProvider`]; which one is
+//! decided once at startup by [`from_env`].
+
+use std::io::Write;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use rocket::serde::{Deserialize, Serialize};
+
+mod deepseek;
+mod llamacpp;
+mod mock;
+mod ollama;
+mod openai;
+mod sse;
+
+pub use deepseek::DeepSeek;
+pub use llamacpp::LlamaCpp;
+pub use mock::MockLlm;
+pub use ollama::Ollama;
+pub use openai::OpenAiCompatible;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Provider handle shared between Rocket's managed state and the CLI.
+pub type SharedLlm = Arc<dyn LlmProvider>;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct Message {
+    pub role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Set on assistant turns that ask for one or more tools to be run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// Set on `tool` messages: which call this is the result of.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl Message {
+    /// A plain text message, e.g. `Message::new("user", "hi")`.
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: Some(content.into()),
+            ..Default::default()
+        }
+    }
+
+    /// The result of `call`, to be sent back to the model.
+    pub fn tool_result(call: &ToolCall, content: impl Into<String>) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: Some(content.into()),
+            tool_call_id: Some(call.id.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct FunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments, exactly as the model produced them.
+    pub arguments: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct Function {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// A tool offered to the model. Only function tools exist today.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct Tool {
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: Function,
+}
+
+impl Tool {
+    pub fn function(function: Function) -> Self {
+        Self {
+            kind: function_type(),
+            function,
+        }
+    }
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+/// One assistant turn as assembled from a provider's stream.
+#[derive(Debug, Clone, Default)]
+pub struct Completion {
+    pub content: Option<String>,
+    /// Possibly several calls in one turn, run in order.
+    pub tool_calls: Vec<ToolCall>,
+    /// `stop`, `length`, `tool_calls`, ... as reported by the server.
+    pub finish_reason: Option<String>,
+}
+
+impl Completion {
+    /// Whether the server cut the reply short at its token limit.
+    pub fn truncated(&self) -> bool {
+        self.finish_reason.as_deref() == Some("length")
+    }
+
+    /// The assistant message to append to the conversation.
+    pub fn into_message(self) -> Message {
+        Message {
+            role: "assistant".to_string(),
+            content: self.content,
+            tool_calls: (!self.tool_calls.is_empty()).then_some(self.tool_calls),
+            ..Default::default()
+        }
+    }
+}
+
+/// Called with each piece of reply text as soon as it streams in.
+pub type OnToken<'a> = &'a (dyn Fn(&str) + Send + Sync);
+
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short name used in logs, e.g. `deepseek`.
+    fn name(&self) -> &str;
+
+    /// Send the conversation (plus any tools the model may call), handing
+    /// reply text to `on_token` as it arrives, and return the whole turn.
+    async fn stream(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<Tool>>,
+        on_token: OnToken<'_>,
+    ) -> Result<Completion, Error>;
+
+    /// Like [`stream`](Self::stream), echoing the reply to the server's
+    /// stdout as it arrives.
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<Tool>>,
+    ) -> Result<Completion, Error> {
+        let echo = |token: &str| {
+            print!("{}", token);
+            let _ = std::io::stdout().flush();
+        };
+        let completion = self.stream(messages, tools, &echo).await;
+        println!(); // Newline after streaming content
+        completion
+    }
+}
+
+/// A rough token count for budgeting context: about four bytes per token
+/// for English. Good enough to decide when to trim, not to bill by.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+/// Build the provider named by `LLM_PROVIDER` (default `deepseek`).
+///
+/// * `deepseek` - needs `DEEPSEEK_API_KEY`; `LLM_MODEL` overrides `deepseek-chat`.
+/// * `openai`   - any OpenAI-compatible `/chat/completions` server; needs
+///   `LLM_BASE_URL` and `LLM_MODEL`, `LLM_API_KEY` is optional.
+/// * `ollama`   - a local Ollama server; needs `LLM_MODEL`, `LLM_BASE_URL`
+///   defaults to `http://localhost:11434`.
+/// * `llamacpp` - a local llama.cpp server; `LLM_BASE_URL` defaults to
+///   `http://localhost:8080`.
+/// * `mock`     - replays the scripted turns in `LLM_MOCK_FIXTURE`.
+pub fn from_env() -> Result<SharedLlm, Error> {
+    let provider = std::env::var("LLM_PROVIDER").unwrap_or_else(|_| "deepseek".to_string());
+
+    match provider.trim().to_lowercase().as_str() {
+        "deepseek" => Ok(Arc::new(DeepSeek::from_env()?)),
+        "openai" | "openai-compatible" => Ok(Arc::new(OpenAiCompatible::from_env()?)),
+        "ollama" => Ok(Arc::new(Ollama::from_env()?)),
+        "llamacpp" | "llama.cpp" => Ok(Arc::new(LlamaCpp::from_env()?)),
+        "mock" => Ok(Arc::new(MockLlm::from_env()?)),
+        other => Err(format!(
+            "unknown LLM_PROVIDER '{other}' (expected deepseek, openai, ollama, llamacpp or mock)"
+        )
+        .into()),
+    }
+}
+
+/// HTTP client shared by every provider: generous overall timeout because
+/// answers are streamed, but fail fast if the server isn't there at all.
+fn http_client() -> Result<reqwest::Client, Error> {
+    Ok(reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(300)) // 5 minute timeout
+        .connect_timeout(std::time::Duration::from_secs(30))
+        .http1_only()
+        .build()?)
+}
+
+/// Pop every complete `\n`-terminated line off the front of `buf`, leaving a
+/// trailing partial line (if any) for the next network chunk.
+fn drain_lines(buf: &mut Vec<u8>) -> Vec<String> {
+    let mut lines = Vec::new();
+    while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+        let line: Vec<u8> = buf.drain(..=pos).collect();
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+    }
+    lines
+}
+
+/// Read a required environment variable with a readable error.
+pub(crate) fn required_env(key: &str) -> Result<String, Error> {
+    std::env::var(key).map_err(|_| format!("{key} must be set in environment").into())
+}
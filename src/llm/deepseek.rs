@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+
+use super::{required_env, Completion, Error, LlmProvider, Message, OnToken, OpenAiCompatible, Tool};
+
+const DEEPSEEK_BASE_URL: &str = "https://api.deepseek.com";
+const DEEPSEEK_DEFAULT_MODEL: &str = "deepseek-chat";
+
+/// The hosted DeepSeek API. It speaks the OpenAI protocol, so this only pins
+/// the endpoint, default model and `DEEPSEEK_API_KEY`.
+pub struct DeepSeek(OpenAiCompatible);
+
+impl DeepSeek {
+    pub fn from_env() -> Result<Self, Error> {
+        let api_key = required_env("DEEPSEEK_API_KEY")?;
+        let model = std::env::var("LLM_MODEL").unwrap_or_else(|_| DEEPSEEK_DEFAULT_MODEL.to_string());
+
+        Ok(Self(OpenAiCompatible::new("deepseek", DEEPSEEK_BASE_URL, model, Some(api_key))?))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for DeepSeek {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    async fn stream(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<Tool>>,
+        on_token: OnToken<'_>,
+    ) -> Result<Completion, Error> {
+        self.0.stream(messages, tools, on_token).await
+    }
+}
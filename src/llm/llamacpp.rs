@@ -0,0 +1,143 @@
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::sse::{SseDecoder, SseEvent};
+use super::{http_client, Completion, Error, LlmProvider, Message, OnToken, Tool};
+
+const LLAMACPP_DEFAULT_URL: &str = "http://localhost:8080";
+
+#[derive(Debug, Serialize)]
+struct CompletionRequest {
+    prompt: String,
+    stream: bool,
+    n_predict: i32,
+    stop: Vec<String>,
+}
+
+/// A local llama.cpp server, spoken to through its native `/completion`
+/// endpoint. That endpoint takes a raw prompt, so the conversation is rendered
+/// with the ChatML template; it has no tool calling, so `search_web` is never
+/// offered to the model.
+pub struct LlamaCpp {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl LlamaCpp {
+    /// Optionally `LLM_BASE_URL`; the server decides which model is loaded.
+    pub fn from_env() -> Result<Self, Error> {
+        let base_url = std::env::var("LLM_BASE_URL").unwrap_or_else(|_| LLAMACPP_DEFAULT_URL.to_string());
+
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: http_client()?,
+        })
+    }
+}
+
+/// Render the conversation as ChatML, ending with an open assistant turn.
+fn chatml_prompt(messages: &[Message]) -> String {
+    let mut prompt = String::new();
+    for message in messages {
+        prompt.push_str(&format!(
+            "<|im_start|>{}\n{}<|im_end|>\n",
+            message.role,
+            message.content.as_deref().unwrap_or("")
+        ));
+    }
+    prompt.push_str("<|im_start|>assistant\n");
+    prompt
+}
+
+/// Add one `/completion` stream event to `completion`, passing its text on.
+fn apply_event(completion: &mut Completion, sse: &SseEvent, on_token: OnToken<'_>) -> Result<(), Error> {
+    let event: Value = serde_json::from_str(&sse.data)?;
+    if let Some(content) = event["content"].as_str().filter(|c| !c.is_empty()) {
+        on_token(content);
+        completion.content.get_or_insert_with(String::new).push_str(content);
+    }
+    if event["stop"].as_bool() == Some(true) {
+        let reason = if event["stopped_limit"].as_bool() == Some(true) { "length" } else { "stop" };
+        completion.finish_reason = Some(reason.to_string());
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl LlmProvider for LlamaCpp {
+    fn name(&self) -> &str {
+        "llamacpp"
+    }
+
+    async fn stream(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<Tool>>,
+        on_token: OnToken<'_>,
+    ) -> Result<Completion, Error> {
+        if tools.is_some() {
+            println!("llamacpp: tool calling is not supported, ignoring tools");
+        }
+
+        let request = CompletionRequest {
+            prompt: chatml_prompt(&messages),
+            stream: true,
+            n_predict: -1,
+            stop: vec!["<|im_end|>".to_string()],
+        };
+
+        println!("Sending request to llamacpp ({}): {:?}", self.base_url, request);
+
+        let response = self
+            .client
+            .post(format!("{}/completion", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        println!("llamacpp API response status: {}", status);
+
+        if !status.is_success() {
+            let error_text = response.text().await?;
+            println!("llamacpp API error response: {}", error_text);
+            return Err(format!("llamacpp API error: {}", status).into());
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut decoder = SseDecoder::new();
+        let mut completion = Completion::default();
+
+        while let Some(item) = stream.next().await {
+            for sse in decoder.feed(&item?) {
+                apply_event(&mut completion, &sse, on_token)?;
+            }
+        }
+        // The connection closed: the last event may not have its blank line
+        if let Some(sse) = decoder.finish() {
+            apply_event(&mut completion, &sse, on_token)?;
+        }
+
+        Ok(completion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_unterminated_last_event_still_counts() {
+        let body = "data: {\"content\":\"Hel\",\"stop\":false}\n\ndata: {\"content\":\"lo\",\"stop\":true,\"stopped_limit\":true}";
+        let mut decoder = SseDecoder::new();
+        let mut completion = Completion::default();
+        let events: Vec<SseEvent> = decoder.feed(body.as_bytes()).into_iter().chain(decoder.finish()).collect();
+        for sse in &events {
+            apply_event(&mut completion, sse, &|_| {}).unwrap();
+        }
+        assert_eq!(completion.content.as_deref(), Some("Hello"));
+        assert_eq!(completion.finish_reason.as_deref(), Some("length"));
+    }
+}
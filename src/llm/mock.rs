@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use rocket::serde::Deserialize;
+
+use super::{required_env, Completion, Error, LlmProvider, Message, OnToken, Tool, ToolCall};
+
+/// One scripted assistant turn: some text, tool calls, or both. Without an
+/// explicit `finish_reason` the turn reports what a real server would.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct MockTurn {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+/// Replays canned assistant turns, in order, instead of calling a model.
+///
+/// The fixture is a JSON array of turns:
+///
+/// ```json
+/// [
+///   { "tool_calls": [{ "id": "call_1", "function": { "name": "search_web", "arguments": "{\"query\": \"rocket sse\"}" } }] },
+///   { "content": "Rocket ships EventStream. <<FINAL_ANSWER>>" }
+/// ]
+/// ```
+///
+/// Every request is recorded so tests can check what the model was shown.
+pub struct MockLlm {
+    turns: Mutex<VecDeque<MockTurn>>,
+    requests: Mutex<Vec<Vec<Message>>>,
+}
+
+impl MockLlm {
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read mock fixture {}: {e}", path.display()))?;
+        let turns: VecDeque<MockTurn> = serde_json::from_str(&raw)
+            .map_err(|e| format!("invalid mock fixture {}: {e}", path.display()))?;
+
+        Ok(Self {
+            turns: Mutex::new(turns),
+            requests: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// `LLM_MOCK_FIXTURE` points at the fixture file.
+    pub fn from_env() -> Result<Self, Error> {
+        Self::from_file(required_env("LLM_MOCK_FIXTURE")?)
+    }
+
+    /// Every conversation sent so far, oldest first.
+    #[cfg(test)]
+    pub fn requests(&self) -> Vec<Vec<Message>> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockLlm {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn stream(
+        &self,
+        messages: Vec<Message>,
+        _tools: Option<Vec<Tool>>,
+        on_token: OnToken<'_>,
+    ) -> Result<Completion, Error> {
+        let mut requests = self.requests.lock().unwrap();
+        requests.push(messages);
+
+        let turn = self
+            .turns
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| format!("mock fixture exhausted after {} turns", requests.len() - 1))?;
+
+        if let Some(content) = &turn.content {
+            on_token(content);
+        }
+
+        let finish_reason = turn.finish_reason.unwrap_or_else(|| {
+            if turn.tool_calls.is_empty() { "stop" } else { "tool_calls" }.to_string()
+        });
+        Ok(Completion {
+            content: turn.content,
+            tool_calls: turn.tool_calls,
+            finish_reason: Some(finish_reason),
+        })
+    }
+}
@@ -0,0 +1,180 @@
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{
+    drain_lines, http_client, required_env, Completion, Error, FunctionCall, LlmProvider, Message, OnToken, Tool, ToolCall,
+};
+
+const OLLAMA_DEFAULT_URL: &str = "http://localhost:11434";
+
+#[derive(Debug, Serialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<Value>>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
+}
+
+/// A local Ollama server, spoken to through its native `/api/chat` endpoint,
+/// which streams one JSON object per line rather than SSE.
+pub struct Ollama {
+    base_url: String,
+    model: String,
+    client: reqwest::Client,
+}
+
+impl Ollama {
+    /// `LLM_MODEL` (e.g. `llama3.1`) and optionally `LLM_BASE_URL`.
+    pub fn from_env() -> Result<Self, Error> {
+        let base_url = std::env::var("LLM_BASE_URL").unwrap_or_else(|_| OLLAMA_DEFAULT_URL.to_string());
+        let model = required_env("LLM_MODEL")?;
+
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+            client: http_client()?,
+        })
+    }
+}
+
+/// Ollama wants tool call arguments as a JSON object rather than a string,
+/// and matches tool results to calls by position instead of by id.
+fn to_ollama_message(message: Message) -> OllamaMessage {
+    let tool_calls = message.tool_calls.map(|calls| {
+        calls
+            .into_iter()
+            .map(|call| {
+                let arguments: Value =
+                    serde_json::from_str(&call.function.arguments).unwrap_or_else(|_| json!({}));
+                json!({ "function": { "name": call.function.name, "arguments": arguments } })
+            })
+            .collect()
+    });
+    OllamaMessage {
+        role: message.role,
+        content: message.content.unwrap_or_default(),
+        tool_calls,
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Ollama {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    async fn stream(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<Tool>>,
+        on_token: OnToken<'_>,
+    ) -> Result<Completion, Error> {
+        let request = OllamaChatRequest {
+            model: self.model.clone(),
+            messages: messages.into_iter().map(to_ollama_message).collect(),
+            stream: true,
+            tools,
+        };
+
+        println!("Sending request to ollama ({}): {:?}", self.base_url, request);
+
+        let response = self
+            .client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        println!("ollama API response status: {}", status);
+
+        if !status.is_success() {
+            let error_text = response.text().await?;
+            println!("ollama API error response: {}", error_text);
+            return Err(format!("ollama API error: {}", status).into());
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut buffer = Vec::new();
+        let mut completion = Completion::default();
+
+        while let Some(item) = stream.next().await {
+            buffer.extend_from_slice(&item?);
+
+            for line in drain_lines(&mut buffer) {
+                apply_event(&mut completion, &line, on_token)?;
+            }
+        }
+        // The stream may end without a newline after the last object
+        let tail = String::from_utf8_lossy(&buffer);
+        if !tail.trim().is_empty() {
+            apply_event(&mut completion, tail.trim(), on_token)?;
+        }
+
+        Ok(completion)
+    }
+}
+
+/// Add one line of the `/api/chat` stream to `completion`, passing its text on.
+fn apply_event(completion: &mut Completion, line: &str, on_token: OnToken<'_>) -> Result<(), Error> {
+    let event: Value = serde_json::from_str(line)?;
+    if let Some(error) = event["error"].as_str() {
+        return Err(format!("ollama error: {error}").into());
+    }
+    if let Some(content) = event["message"]["content"].as_str().filter(|c| !c.is_empty()) {
+        on_token(content);
+        completion.content.get_or_insert_with(String::new).push_str(content);
+    }
+    // Ollama sends each tool call whole, with `arguments` as an
+    // object and no id, so ids are made up to pair results later.
+    for call in event["message"]["tool_calls"].as_array().into_iter().flatten() {
+        completion.tool_calls.push(ToolCall {
+            id: format!("call_{}", completion.tool_calls.len()),
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: call["function"]["name"].as_str().unwrap_or_default().to_string(),
+                arguments: call["function"]["arguments"].to_string(),
+            },
+        });
+    }
+    if let Some(reason) = event["done_reason"].as_str() {
+        completion.finish_reason = Some(reason.to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_unterminated_last_event_still_counts() {
+        let mut buffer = concat!(
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"lo\",\"tool_calls\":[{\"function\":{\"name\":\"search_web\",\"arguments\":{\"query\":\"rocket sse\"}}}]},\"done\":true,\"done_reason\":\"stop\"}",
+        )
+        .as_bytes()
+        .to_vec();
+        let mut completion = Completion::default();
+        for line in drain_lines(&mut buffer) {
+            apply_event(&mut completion, &line, &|_| {}).unwrap();
+        }
+        assert_eq!(completion.content.as_deref(), Some("Hel"));
+
+        apply_event(&mut completion, String::from_utf8_lossy(&buffer).trim(), &|_| {}).unwrap();
+        assert_eq!(completion.content.as_deref(), Some("Hello"));
+        assert_eq!(completion.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(completion.tool_calls[0].function.arguments, r#"{"query":"rocket sse"}"#);
+    }
+}
@@ -0,0 +1,303 @@
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use rocket::http::hyper::body::Bytes;
+use serde::Serialize;
+
+use super::sse::{SseDecoder, SseEvent};
+use super::{http_client, required_env, Completion, Error, FunctionCall, LlmProvider, Message, OnToken, Tool, ToolCall};
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest {
+    model: String,
+    messages: Vec<Message>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<String>,
+}
+
+/// Any server speaking the OpenAI `/chat/completions` streaming protocol.
+pub struct OpenAiCompatible {
+    name: String,
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+}
+
+impl OpenAiCompatible {
+    pub fn new(
+        name: impl Into<String>,
+        base_url: impl Into<String>,
+        model: impl Into<String>,
+        api_key: Option<String>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            name: name.into(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            model: model.into(),
+            api_key,
+            client: http_client()?,
+        })
+    }
+
+    /// `LLM_BASE_URL` (e.g. `https://api.openai.com/v1`), `LLM_MODEL` and an
+    /// optional `LLM_API_KEY`.
+    pub fn from_env() -> Result<Self, Error> {
+        let base_url = required_env("LLM_BASE_URL")?;
+        let model = required_env("LLM_MODEL")?;
+        let api_key = std::env::var("LLM_API_KEY").ok().filter(|k| !k.is_empty());
+
+        Self::new("openai", base_url, model, api_key)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiCompatible {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn stream(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<Tool>>,
+        on_token: OnToken<'_>,
+    ) -> Result<Completion, Error> {
+        let request = ChatCompletionRequest {
+            model: self.model.clone(),
+            messages,
+            stream: true,  // Enable streaming
+            tool_choice: tools.as_ref().map(|_| "auto".to_string()),
+            tools,
+        };
+
+        println!("Sending request to {} ({}): {:?}", self.name, self.base_url, request);
+
+        let mut builder = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .header("Content-Type", "application/json")
+            .json(&request);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.header("Authorization", format!("Bearer {}", api_key));
+        }
+        let response = builder.send().await?;
+
+        let status = response.status();
+        println!("{} API response status: {}", self.name, status);
+
+        if !status.is_success() {
+            let error_text = response.text().await?;
+            println!("{} API error response: {}", self.name, error_text);
+            return Err(format!("{} API error: {}", self.name, status).into());
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut completion = CompletionStream::default();
+
+        while !completion.is_done()
+            && let Some(item) = stream.next().await
+        {
+            let chunk: Bytes = item?;
+            let content = completion.feed(&chunk)?;
+            if !content.is_empty() {
+                on_token(&content);
+            }
+        }
+
+        completion.finish()
+    }
+}
+
+/// Builds a [`Completion`] from a `/chat/completions` SSE body as the network
+/// chunks arrive.
+#[derive(Default)]
+struct CompletionStream {
+    decoder: SseDecoder,
+    completion: Completion,
+    done: bool,
+}
+
+impl CompletionStream {
+    /// Whether `data: [DONE]` has been seen; anything after it is ignored.
+    fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Feed one network chunk, returning the content text it added.
+    fn feed(&mut self, chunk: &[u8]) -> Result<String, Error> {
+        let events = self.decoder.feed(chunk);
+        self.apply(events)
+    }
+
+    /// The connection closed: flush any unterminated event and hand back the
+    /// assembled turn.
+    fn finish(mut self) -> Result<Completion, Error> {
+        let tail = self.decoder.finish();
+        self.apply(tail)?;
+        Ok(self.completion)
+    }
+
+    fn apply(&mut self, events: impl IntoIterator<Item = SseEvent>) -> Result<String, Error> {
+        let mut content = String::new();
+        for event in events {
+            if self.done {
+                break;
+            }
+            if event.is_done() {
+                self.done = true;
+                break;
+            }
+
+            let data: serde_json::Value = serde_json::from_str(&event.data)
+                .map_err(|e| format!("malformed stream event {:?}: {e}", event.data))?;
+            if event.event == "error" || !data["error"].is_null() {
+                return Err(format!("stream error: {}", data["error"]).into());
+            }
+            for choice in data["choices"].as_array().into_iter().flatten() {
+                if let Some(text) = apply_delta(&mut self.completion, choice) {
+                    content.push_str(text);
+                }
+            }
+        }
+        Ok(content)
+    }
+}
+
+/// Fold one streamed `choices[]` entry into `completion`, returning any new
+/// content text. Each tool call arrives as an `index`ed id and name followed
+/// by argument fragments that only parse as JSON once concatenated; several
+/// calls may be interleaved in one turn.
+fn apply_delta<'a>(completion: &mut Completion, choice: &'a serde_json::Value) -> Option<&'a str> {
+    let delta = &choice["delta"];
+
+    for call in delta["tool_calls"].as_array().into_iter().flatten() {
+        let index = call["index"].as_u64().unwrap_or(0) as usize;
+        while completion.tool_calls.len() <= index {
+            completion.tool_calls.push(ToolCall {
+                id: String::new(),
+                kind: "function".to_string(),
+                function: FunctionCall::default(),
+            });
+        }
+        let tool_call = &mut completion.tool_calls[index];
+        if let Some(id) = call["id"].as_str() {
+            tool_call.id.push_str(id);
+        }
+        if let Some(name) = call["function"]["name"].as_str() {
+            tool_call.function.name.push_str(name);
+        }
+        if let Some(arguments) = call["function"]["arguments"].as_str() {
+            tool_call.function.arguments.push_str(arguments);
+        }
+    }
+
+    if let Some(reason) = choice["finish_reason"].as_str() {
+        completion.finish_reason = Some(reason.to_string());
+    }
+
+    let content = delta["content"].as_str().filter(|c| !c.is_empty())?;
+    completion.content.get_or_insert_with(String::new).push_str(content);
+    Some(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parallel_tool_calls_are_joined_across_deltas() {
+        let deltas = [
+            json!({ "delta": { "role": "assistant", "tool_calls": [
+                { "index": 0, "id": "call_a", "type": "function", "function": { "name": "search_web", "arguments": "" } }
+            ] } }),
+            json!({ "delta": { "tool_calls": [{ "index": 0, "function": { "arguments": "{\"que" } }] } }),
+            json!({ "delta": { "tool_calls": [
+                { "index": 1, "id": "call_b", "type": "function", "function": { "name": "search_web", "arguments": "{\"query\": \"axum sse\"}" } }
+            ] } }),
+            json!({ "delta": { "tool_calls": [{ "index": 0, "function": { "arguments": "ry\": \"rocket sse\"}" } }] } }),
+            json!({ "delta": {}, "finish_reason": "tool_calls" }),
+        ];
+
+        let mut completion = Completion::default();
+        for choice in &deltas {
+            assert_eq!(apply_delta(&mut completion, choice), None);
+        }
+
+        let calls: Vec<_> = completion
+            .tool_calls
+            .iter()
+            .map(|c| (c.id.as_str(), c.function.name.as_str(), c.function.arguments.as_str()))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("call_a", "search_web", r#"{"query": "rocket sse"}"#),
+                ("call_b", "search_web", r#"{"query": "axum sse"}"#),
+            ]
+        );
+        assert_eq!(completion.content, None);
+        assert_eq!(completion.finish_reason.as_deref(), Some("tool_calls"));
+    }
+
+    fn replay(fixture: &str, chunk_size: usize) -> Completion {
+        let path = format!("{}/tests/fixtures/{fixture}", env!("CARGO_MANIFEST_DIR"));
+        let body = std::fs::read(path).unwrap();
+
+        let mut stream = CompletionStream::default();
+        let mut streamed = String::new();
+        for chunk in body.chunks(chunk_size) {
+            streamed.push_str(&stream.feed(chunk).unwrap());
+        }
+        let completion = stream.finish().unwrap();
+        assert_eq!(completion.content.as_deref().unwrap_or(""), streamed);
+        completion
+    }
+
+    #[test]
+    fn recorded_deepseek_content_stream() {
+        for chunk_size in [1, 3, 17, 64, 4096] {
+            let completion = replay("deepseek_stream_content.sse", chunk_size);
+            assert_eq!(
+                completion.content.as_deref(),
+                Some("Use `reqwest` for fetching → `scraper` for parsing. Ship it 🚀"),
+                "chunk size {chunk_size}"
+            );
+            assert!(completion.tool_calls.is_empty());
+            assert_eq!(completion.finish_reason.as_deref(), Some("stop"));
+        }
+    }
+
+    #[test]
+    fn recorded_deepseek_tool_call_stream() {
+        for chunk_size in [1, 5, 29, 4096] {
+            let completion = replay("deepseek_stream_tool_calls.sse", chunk_size);
+            assert_eq!(completion.tool_calls.len(), 1, "chunk size {chunk_size}");
+            let call = &completion.tool_calls[0];
+            assert_eq!(call.id, "call_0_4f0e4b6c-2b9d-4d0f-9a53-1d3b7c9e0a11");
+            assert_eq!(call.function.name, "search_web");
+            assert_eq!(call.function.arguments, r#"{"query":"rocket EventStream example"}"#);
+            assert_eq!(completion.finish_reason.as_deref(), Some("tool_calls"));
+        }
+    }
+
+    #[test]
+    fn events_after_done_are_ignored() {
+        let mut stream = CompletionStream::default();
+        let body = b"data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\ndata: [DONE]\n\ndata: garbage\n\n";
+        assert_eq!(stream.feed(body).unwrap(), "hi");
+        assert!(stream.is_done());
+        assert_eq!(stream.finish().unwrap().content.as_deref(), Some("hi"));
+    }
+
+    #[test]
+    fn error_events_surface_as_errors() {
+        let mut stream = CompletionStream::default();
+        let body = b"data: {\"error\":{\"message\":\"Insufficient Balance\"}}\n\n";
+        let err = stream.feed(body).unwrap_err();
+        assert!(err.to_string().contains("Insufficient Balance"));
+    }
+}
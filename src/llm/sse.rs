@@ -0,0 +1,161 @@
+//! Incremental decoder for `text/event-stream` bodies.
+//!
+//! Network chunks are arbitrary byte slices: an event (or a multi-byte UTF-8
+//! character) can be split across any number of them, so bytes are buffered
+//! until a full line is available and events are only emitted once their
+//! terminating blank line has arrived.
+
+/// One dispatched event. `event` is `message` unless the server named it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: String,
+    pub data: String,
+}
+
+impl SseEvent {
+    /// OpenAI-style servers end the stream with `data: [DONE]`.
+    pub fn is_done(&self) -> bool {
+        self.data == "[DONE]"
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the next network chunk, returning every event it completed.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n' || b == b'\r') {
+            // A `\r` at the very end may be the first half of a `\r\n`
+            // split across chunks; wait for the next byte to decide.
+            if self.buffer[end] == b'\r' && end + 1 == self.buffer.len() {
+                break;
+            }
+            let terminator = if self.buffer[end] == b'\r' && self.buffer[end + 1] == b'\n' { 2 } else { 1 };
+
+            let line: Vec<u8> = self.buffer.drain(..end + terminator).take(end).collect();
+            if let Some(event) = self.process_line(&String::from_utf8_lossy(&line)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flush an event the server didn't terminate with a blank line before
+    /// closing the connection.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            let line = String::from_utf8_lossy(&line);
+            if let Some(event) = self.process_line(line.trim_end_matches('\r')) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None; // comment, e.g. `: keep-alive`
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => {} // `id`, `retry` and unknown fields don't matter to us
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(SseEvent {
+            event: event.unwrap_or_else(|| "message".to_string()),
+            data: std::mem::take(&mut self.data).join("\n"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_in_chunks(input: &[u8], chunk_size: usize) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        let mut events: Vec<_> = input.chunks(chunk_size).flat_map(|c| decoder.feed(c)).collect();
+        events.extend(decoder.finish());
+        events
+    }
+
+    fn data(events: &[SseEvent]) -> Vec<&str> {
+        events.iter().map(|e| e.data.as_str()).collect()
+    }
+
+    #[test]
+    fn events_split_across_chunks_are_reassembled() {
+        let input = b"data: {\"a\":1}\n\ndata: {\"b\":2}\n\ndata: [DONE]\n\n";
+        for size in 1..input.len() {
+            let events = decode_in_chunks(input, size);
+            assert_eq!(data(&events), [r#"{"a":1}"#, r#"{"b":2}"#, "[DONE]"], "chunk size {size}");
+            assert!(events[2].is_done());
+        }
+    }
+
+    #[test]
+    fn crlf_comments_and_named_events() {
+        let input = b": keep-alive\r\n\r\nevent: error\r\ndata: boom\r\n\r\ndata: ok\r\n\r\n";
+        for size in 1..input.len() {
+            let events = decode_in_chunks(input, size);
+            assert_eq!(
+                events,
+                [
+                    SseEvent { event: "error".to_string(), data: "boom".to_string() },
+                    SseEvent { event: "message".to_string(), data: "ok".to_string() },
+                ],
+                "chunk size {size}"
+            );
+        }
+    }
+
+    #[test]
+    fn multi_line_data_is_joined_with_newlines() {
+        let events = decode_in_chunks(b"data: first\ndata:second\nid: 7\n\n", 4);
+        assert_eq!(data(&events), ["first\nsecond"]);
+    }
+
+    #[test]
+    fn multi_byte_utf8_split_across_chunks() {
+        let input = "data: {\"content\":\"plan → ship 🚀\"}\n\n".as_bytes();
+        for size in 1..8 {
+            let events = decode_in_chunks(input, size);
+            assert_eq!(data(&events), [r#"{"content":"plan → ship 🚀"}"#], "chunk size {size}");
+        }
+    }
+
+    #[test]
+    fn unterminated_final_event_is_flushed() {
+        let events = decode_in_chunks(b"data: tail", 3);
+        assert_eq!(data(&events), ["tail"]);
+    }
+}
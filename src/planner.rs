@@ -0,0 +1,910 @@
+//! The planning pipeline behind `/planner/create_plan`: clarifying
+//! questions, then a search-driven research loop, then the final plan.
+
+use futures::future::BoxFuture;
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::llm::{estimate_tokens, Error, Function, LlmProvider, Message, Tool};
+use crate::{diagrams, structured_plan};
+
+/// Runs one `search_web` call and returns the pages it read, reporting each
+/// page it fetches along the way.
+pub type SearchFn = dyn for<'a> Fn(&'a str, OnEvent<'a>) -> BoxFuture<'a, Vec<Page>> + Send + Sync;
+
+/// Receives progress as the pipeline runs.
+pub type OnEvent<'a> = &'a (dyn Fn(PlanEvent) + Send + Sync);
+
+/// Receives the research state after every completed research turn.
+pub type OnCheckpoint<'a> = &'a (dyn Fn(&ResearchState) + Send + Sync);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum PlanStage {
+    Questions,
+    Research,
+    Plan,
+}
+
+/// How the final plan is written. `json` produces a document matching
+/// `static/plan.schema.json`; the question round is always plain text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum PlanFormat {
+    #[default]
+    Markdown,
+    /// Markdown with a Mermaid timeline and dependency chart appended. The
+    /// charts cost another model call, so they are only drawn when asked for.
+    MarkdownWithDiagrams,
+    Json,
+}
+
+/// What the planner is doing right now. Serialized with a `type` tag, e.g.
+/// `{"type": "search", "number": 3, "query": "..."}`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum PlanEvent {
+    /// A new stage of the pipeline has started.
+    Stage { stage: PlanStage },
+    /// The researcher issued search number `number`.
+    Search { number: usize, query: String },
+    /// A result page was downloaded for the current search.
+    PageFetched { url: String },
+    /// A result page could not be downloaded.
+    PageFailed { url: String, error: String },
+    /// A result page was left alone because the site's robots.txt keeps us
+    /// out (or couldn't be read).
+    PageSkipped { url: String, reason: String },
+    /// The researcher moved on to a new research phase.
+    Phase { phase: ResearchPhase },
+    /// Foundational research picked out these components to study.
+    Components { components: Vec<String> },
+    /// The researcher appended a phase's notes to the knowledge base.
+    Synthesis { text: String },
+    /// Older search results were summarized to keep the researcher's
+    /// conversation small; sizes are estimated tokens.
+    Compacted { before: usize, after: usize },
+    /// Reply text (questions or the final plan) as it streams in.
+    Token { content: String },
+}
+
+/// The readable text of one search result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub url: String,
+    pub title: Option<String>,
+    pub content: String,
+}
+
+/// A page the researcher was shown, numbered so the notes and the plan can
+/// cite it as `[n]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Source {
+    pub number: usize,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Research budget for one plan, across all phases.
+const MAX_SEARCHES: usize = 50;
+
+/// Components past this many are left to the overall synthesis.
+const MAX_COMPONENTS: usize = 6;
+
+/// Research turns for one plan: every search in its own turn plus every
+/// phase's synthesis, with room to spare. A model that keeps calling tools
+/// past this is moved on to the overall synthesis.
+const MAX_RESEARCH_TURNS: usize = 80;
+
+/// Estimated size of the researcher's conversation past which older search
+/// results are summarized; `RESEARCH_CONTEXT_TOKENS` overrides it.
+const COMPACT_AFTER_TOKENS: usize = 32_000;
+
+/// Starts the search result that holds the summary of the ones before it.
+const SUMMARY_HEADING: &str = "Summary of earlier search results:";
+
+/// Stands in for a search result that has been summarized.
+const COMPACTED: &str = "(summarized in an earlier search result)";
+
+/// Where the researcher is. Serialized with a `name` tag, e.g.
+/// `{"name": "component_analysis", "component": "Storage"}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "name", content = "component", rename_all = "snake_case")]
+pub enum ResearchPhase {
+    /// Core concepts and established approaches for the goal as a whole.
+    #[default]
+    Foundational,
+    /// Specs, case studies, benchmarks and alternatives for one component.
+    ComponentAnalysis(String),
+    /// Pulling the per-component findings together, without searching.
+    Synthesis,
+}
+
+impl ResearchPhase {
+    /// The instruction the researcher gets when the phase starts.
+    fn brief(&self) -> String {
+        match self {
+            ResearchPhase::Foundational => "Phase: foundational research. Search for the core concepts, \
+                established approaches and hard constraints behind this goal. Once you understand the \
+                problem space, reply without calling any tool with a short synthesis of what you learned."
+                .to_string(),
+            ResearchPhase::ComponentAnalysis(component) => format!(
+                "Phase: component analysis of **{component}**. Search for its technical specifications, \
+                case studies and real-world examples, performance benchmarks, and alternative approaches. \
+                Then reply without calling any tool with a synthesis for this component only."
+            ),
+            ResearchPhase::Synthesis => "Phase: synthesis. Searching is over. Reconcile the findings \
+                across all components: decisions, trade-offs, risks and open questions. End your reply \
+                with <<FINAL_ANSWER>>."
+                .to_string(),
+        }
+    }
+}
+
+/// Also the heading for the phase's notes in the knowledge base.
+impl std::fmt::Display for ResearchPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResearchPhase::Foundational => write!(f, "Foundational research"),
+            ResearchPhase::ComponentAnalysis(component) => write!(f, "Component: {component}"),
+            ResearchPhase::Synthesis => write!(f, "Overall synthesis"),
+        }
+    }
+}
+
+/// Everything the researcher knows and where it is, checkpointed between
+/// turns so resuming never repeats a finished search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ResearchState {
+    #[serde(default)]
+    pub phase: ResearchPhase,
+    /// Parts of the project picked out after foundational research.
+    #[serde(default)]
+    pub components: Vec<String>,
+    /// The researcher's conversation, tool calls and results included.
+    pub messages: Vec<Message>,
+    pub knowledge_base: String,
+    pub search_count: usize,
+    /// Every page read so far; a page found again keeps its first number.
+    #[serde(default)]
+    pub sources: Vec<Source>,
+    /// Research turns taken so far, across all phases.
+    #[serde(default)]
+    pub turns: usize,
+}
+
+impl ResearchState {
+    /// The citation number for `url`, adding it as a new source if needed.
+    fn cite(&mut self, page: &Page) -> usize {
+        if let Some(source) = self.sources.iter().find(|s| s.url == page.url) {
+            return source.number;
+        }
+        let number = self.sources.len() + 1;
+        self.sources.push(Source { number, url: page.url.clone(), title: page.title.clone() });
+        number
+    }
+
+    /// The phase after the current one: each component in turn, then synthesis.
+    fn next_phase(&self) -> ResearchPhase {
+        let next_component = match &self.phase {
+            ResearchPhase::Foundational => self.components.first(),
+            ResearchPhase::ComponentAnalysis(current) => self
+                .components
+                .iter()
+                .position(|c| c == current)
+                .and_then(|i| self.components.get(i + 1)),
+            ResearchPhase::Synthesis => None,
+        };
+        next_component
+            .map(|c| ResearchPhase::ComponentAnalysis(c.clone()))
+            .unwrap_or(ResearchPhase::Synthesis)
+    }
+}
+
+/// Run one round of the planning conversation.
+///
+/// With only the user's goal in `conversation` this asks the clarifying
+/// questions; once the answers are in it researches (calling `search` for
+/// every `search_web` request) and writes the final plan in `format`.
+/// Progress goes to `on_event`; the return value is the full reply, or the
+/// error that ended the run.
+pub async fn create_plan(
+    llm: &dyn LlmProvider,
+    conversation: &[Message],
+    format: PlanFormat,
+    search: &SearchFn,
+    on_event: OnEvent<'_>,
+) -> String {
+    run_plan(llm, conversation, format, None, search, on_event, &|_| {}).await.unwrap_or_else(|e| e.to_string())
+}
+
+/// [`create_plan`], but research starts from `resume` when given,
+/// `on_checkpoint` is handed the research state after every turn so an
+/// interrupted run can be picked up again, and a failed run is an `Err`.
+pub async fn run_plan(
+    llm: &dyn LlmProvider,
+    conversation: &[Message],
+    format: PlanFormat,
+    resume: Option<ResearchState>,
+    search: &SearchFn,
+    on_event: OnEvent<'_>,
+    on_checkpoint: OnCheckpoint<'_>,
+) -> Result<String, Error> {
+    let on_token = |token: &str| on_event(PlanEvent::Token { content: token.to_string() });
+
+    // ------------------------------------------------------------------
+    // 0. Sanity helpers
+    // ------------------------------------------------------------------
+    let user_goal = conversation
+        .first()
+        .and_then(|m| m.content.as_deref())
+        .unwrap_or("")
+        .trim();
+    if user_goal.is_empty() {
+        return Err("Error: empty prompt".into());
+    }
+
+    // ------------------------------------------------------------------
+    // 1. QUESTION PHASE (6–7 questions)  -------------------------------
+    // ------------------------------------------------------------------
+    if conversation.len() == 1 && resume.is_none() {
+        on_event(PlanEvent::Stage { stage: PlanStage::Questions });
+        let system_prompt = r#"
+You are **PlanBot**.
+Your ONLY job right now is to ask the user **exactly six** crisp, high-impact questions that will let you write a bullet-proof technical plan later.
+
+Rules:
+- One question per line, no numbering.
+- Do NOT greet or explain.
+- Do NOT ask more than six questions.
+"#.trim();
+
+        let msgs = vec![
+            Message::new("system", system_prompt),
+            Message::new("user", user_goal),
+        ];
+
+        return match llm.stream(msgs, None, &on_token).await {
+            Ok(reply) => Ok(reply.content.unwrap_or_default()),
+            Err(e) => Err(format!("api error: {e}").into()),
+        };
+    }
+
+    // ------------------------------------------------------------------
+    // 2. RESEARCH PHASE  ------------------------------------------------
+    // ------------------------------------------------------------------
+    on_event(PlanEvent::Stage { stage: PlanStage::Research });
+
+    // Kick-off prompt for DeepSeek
+    let kickoff = vec![
+        Message::new(
+            "system",
+            r#"
+You are **PlanBot-researcher**.
+You will be given the user’s goal + answers to your 6 questions.
+Your job: research in phases until you possess **enough** information to write the final plan.
+
+The phases are foundational research, then one analysis per key component of
+the project, then an overall synthesis. Each phase starts with a message
+telling you what to look into.
+
+Workflow inside a phase:
+1. Decide what you still need to know.
+2. Call the `search_web` tool with a sharp query (several calls at once are fine for independent questions).
+3. Read the returned snippets.
+4. Repeat until the phase is covered, then reply without calling any tool: that reply is the phase's synthesis.
+
+Every result page is numbered, e.g. `[3] URL: ...`. In your syntheses, back each
+claim with the numbers of the pages it came from, like "COPY is fastest [3][7]".
+
+You may perform at most 50 searches across all phases.
+"#,
+        ),
+    ];
+
+    let mut state = resume.unwrap_or_else(|| {
+        let mut messages = kickoff;
+        messages.extend_from_slice(conversation);
+        messages.push(Message::new("user", ResearchPhase::Foundational.brief()));
+        ResearchState {
+            phase: ResearchPhase::Foundational,
+            components: Vec::new(),
+            messages,
+            knowledge_base: String::new(),
+            search_count: 0,
+            sources: Vec::new(),
+            turns: 0,
+        }
+    });
+    on_event(PlanEvent::Phase { phase: state.phase.clone() });
+
+    let search_tool = vec![Tool::function(Function {
+        name: "search_web".to_string(),
+        description: "Search the web (or the configured document collection) and return the most relevant \
+            passages of the top results"
+            .to_string(),
+        parameters: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string" }
+            },
+            "required": ["query"]
+        }),
+    })];
+
+    let compact_after = std::env::var("RESEARCH_CONTEXT_TOKENS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(COMPACT_AFTER_TOKENS);
+
+    while !state.knowledge_base.contains("<<FINAL_ANSWER>>") {
+        if state.turns >= MAX_RESEARCH_TURNS && state.phase != ResearchPhase::Synthesis {
+            println!("Research hit its {MAX_RESEARCH_TURNS}-turn limit; moving on to the synthesis");
+            state.phase = ResearchPhase::Synthesis;
+            state.messages.push(Message::new("user", state.phase.brief()));
+            on_event(PlanEvent::Phase { phase: state.phase.clone() });
+        } else if state.turns > MAX_RESEARCH_TURNS {
+            // Even the synthesis turn went to tool calls: plan from what there is
+            println!("Research stopped after {} turns without a synthesis", state.turns);
+            state.knowledge_base.push_str(&format!(
+                "\n## {}\n(research stopped after {} turns)\n<<FINAL_ANSWER>>\n",
+                state.phase, state.turns
+            ));
+            on_checkpoint(&state);
+            break;
+        }
+        state.turns += 1;
+
+        if conversation_tokens(&state.messages) > compact_after && compact(llm, &mut state, on_event).await {
+            on_checkpoint(&state);
+        }
+
+        // Synthesis works from what was found; so does everything once the budget is spent
+        let tools = (state.phase != ResearchPhase::Synthesis && state.search_count < MAX_SEARCHES)
+            .then(|| search_tool.clone());
+        let completion = match llm.stream(state.messages.clone(), tools, &|_| {}).await {
+            Ok(c) => c,
+            Err(e) => return Err(format!("{} error: {e}", llm.name()).into()),
+        };
+        if completion.truncated() {
+            println!("Research turn hit the model's token limit; continuing with partial reply");
+        }
+        let assistant_msg = completion.into_message();
+
+        // Case 1: the model wants to search, possibly several queries at once
+        if let Some(calls) = assistant_msg.tool_calls.clone() {
+            state.messages.push(assistant_msg);
+
+            // Every call needs a matching tool message, even ones we refuse
+            for call in &calls {
+                let result = if call.function.name != "search_web" {
+                    format!("Unknown tool: {}", call.function.name)
+                } else if state.phase == ResearchPhase::Synthesis {
+                    "Searching is closed in the synthesis phase; write it from the knowledge base.".to_string()
+                } else if state.search_count >= MAX_SEARCHES {
+                    "Search budget exhausted; write up what you have.".to_string()
+                } else {
+                    let args: serde_json::Value = serde_json::from_str(&call.function.arguments)
+                        .unwrap_or_else(|_| serde_json::json!({}));
+                    let query = args["query"].as_str().unwrap_or("").to_string();
+                    state.search_count += 1;
+
+                    state.knowledge_base.push_str(&format!(
+                        "\n--- Search #{}: {query} ---\n",
+                        state.search_count
+                    ));
+                    on_event(PlanEvent::Search { number: state.search_count, query: query.clone() });
+                    let pages = search(&query, on_event).await;
+                    if pages.is_empty() {
+                        "No content found".to_string()
+                    } else {
+                        let mut numbered = Vec::new();
+                        let mut result = String::new();
+                        for page in &pages {
+                            let number = state.cite(page);
+                            numbered.push(format!("[{number}] {}", page.url));
+                            result.push_str(&format!("[{number}] URL: {}\n", page.url));
+                            if let Some(title) = &page.title {
+                                result.push_str(&format!("Title: {title}\n"));
+                            }
+                            result.push_str(&format!("Content: {}\n\n", page.content));
+                        }
+                        state.knowledge_base.push_str(&format!("Sources: {}\n", numbered.join(", ")));
+                        result
+                    }
+                };
+                state.messages.push(Message::tool_result(call, result));
+            }
+            on_checkpoint(&state);
+            continue;
+        }
+
+        // Case 2: no searches, so this is the phase's synthesis
+        let mut section = format!(
+            "\n## {}\n{}\n",
+            state.phase,
+            assistant_msg.content.as_deref().unwrap_or("").trim()
+        );
+        if state.phase == ResearchPhase::Synthesis && !section.contains("<<FINAL_ANSWER>>") {
+            section.push_str("<<FINAL_ANSWER>>\n");
+        }
+        state.knowledge_base.push_str(&section);
+        state.messages.push(assistant_msg);
+        on_event(PlanEvent::Synthesis { text: section });
+
+        if state.phase == ResearchPhase::Foundational {
+            state.components = identify_components(llm, &state.knowledge_base).await;
+            on_event(PlanEvent::Components { components: state.components.clone() });
+        }
+        if state.phase != ResearchPhase::Synthesis {
+            state.phase = state.next_phase();
+            state.messages.push(Message::new("user", state.phase.brief()));
+            on_event(PlanEvent::Phase { phase: state.phase.clone() });
+        }
+        on_checkpoint(&state);
+    }
+
+    // ------------------------------------------------------------------
+    // 3. PLAN PHASE  ----------------------------------------------------
+    // ------------------------------------------------------------------
+    on_event(PlanEvent::Stage { stage: PlanStage::Plan });
+    if format == PlanFormat::Json {
+        let mut plan = match structured_plan::write(llm, &state.knowledge_base).await {
+            Ok(plan) => plan,
+            Err(e) => return Err(format!("structured plan error: {e}").into()),
+        };
+        if !state.sources.is_empty() {
+            let cited_in = serde_json::to_string(&plan).unwrap_or_default();
+            plan.sources = referenced(&state.sources, &cited_in)
+                .into_iter()
+                .map(|s| structured_plan::Source { number: Some(s.number), title: s.title.clone(), url: s.url.clone() })
+                .collect();
+        }
+        return Ok(serde_json::to_string_pretty(&plan)?);
+    }
+
+    let final_prompt = vec![
+        Message::new(
+            "system",
+            "You are **PlanBot-final**.  \
+            Using the knowledge base below, write a **comprehensive technical plan** \
+            with clear sections, timelines, and deliverables. Cite the sources behind \
+            each decision by their numbers from the knowledge base, like [3] or [3][7]. \
+            Don't list the sources at the end; that is done for you.",
+        ),
+        Message::new("user", state.knowledge_base),
+    ];
+
+    let reply = match llm.stream(final_prompt, None, &on_token).await {
+        Ok(reply) => reply,
+        Err(e) => return Err(format!("final api error: {e}").into()),
+    };
+    let truncated = reply.truncated();
+    let mut plan = reply.content.unwrap_or_default();
+    if truncated {
+        plan.push_str("\n\n_(plan truncated: the model hit its output token limit)_");
+    }
+
+    // Timelines come back as prose: pull the schedule out and draw it
+    if format == PlanFormat::MarkdownWithDiagrams {
+        match structured_plan::write(llm, &plan).await {
+            Ok(structured) => {
+                let charts = diagrams::markdown(&structured);
+                on_token(&charts);
+                plan.push_str(&charts);
+            }
+            Err(e) => println!("Could not extract a schedule for the plan diagrams: {e}"),
+        }
+    }
+
+    if !state.sources.is_empty() {
+        let sources = bibliography(&referenced(&state.sources, &plan));
+        on_token(&sources);
+        plan.push_str(&sources);
+    }
+    Ok(plan)
+}
+
+/// Estimated tokens of a conversation, tool call arguments included.
+fn conversation_tokens(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|m| {
+            let calls: usize = m.tool_calls.iter().flatten().map(|c| estimate_tokens(&c.function.arguments)).sum();
+            estimate_tokens(m.content.as_deref().unwrap_or("")) + calls
+        })
+        .sum()
+}
+
+/// Replace every search result before the latest turn's with one summary
+/// written by the model, so a long run stays inside its context window.
+///
+/// The results stay in place (each tool call still needs its answer): the
+/// first holds the summary and the rest a pointer to it. A summary that
+/// drops a citation gets the source listed after it, so the researcher can
+/// still cite every page it read. Returns whether anything changed.
+async fn compact(llm: &dyn LlmProvider, state: &mut ResearchState, on_event: OnEvent<'_>) -> bool {
+    let latest_turn = state.messages.iter().rposition(|m| m.tool_calls.is_some()).unwrap_or(0);
+    let older: Vec<usize> = (0..latest_turn)
+        .filter(|&i| state.messages[i].role == "tool" && state.messages[i].content.as_deref() != Some(COMPACTED))
+        .collect();
+    let only_summary = older.len() == 1
+        && state.messages[older[0]].content.as_deref().is_some_and(|c| c.starts_with(SUMMARY_HEADING));
+    if older.is_empty() || only_summary {
+        return false;
+    }
+
+    let results: Vec<&str> = older.iter().filter_map(|&i| state.messages[i].content.as_deref()).collect();
+    let results = results.join("\n\n");
+    let prompt = vec![
+        Message::new(
+            "system",
+            "You are **PlanBot-archivist**. Condense the search results below into notes for a \
+            researcher who can no longer see them. Keep every fact, figure, name and version that \
+            could matter for a technical plan, and keep the page numbers: end each claim with the \
+            numbers of the pages it came from, like [3] or [3][7]. Reply with the notes only.",
+        ),
+        Message::new("user", results.as_str()),
+    ];
+    let summary = match llm.stream(prompt, None, &|_| {}).await {
+        Ok(reply) => reply.content.unwrap_or_default(),
+        Err(e) => {
+            println!("Could not summarize earlier search results, keeping them: {e}");
+            return false;
+        }
+    };
+
+    let (read, kept) = (cited_numbers(&results), cited_numbers(&summary));
+    let dropped: Vec<String> = state
+        .sources
+        .iter()
+        .filter(|s| read.contains(&s.number) && !kept.contains(&s.number))
+        .map(|s| format!("[{}] {}", s.number, s.url))
+        .collect();
+    let mut summary = format!("{SUMMARY_HEADING}\n{}", summary.trim());
+    if !dropped.is_empty() {
+        summary.push_str(&format!("\n\nAlso read: {}", dropped.join(", ")));
+    }
+
+    let before = conversation_tokens(&state.messages);
+    state.messages[older[0]].content = Some(summary);
+    for &i in &older[1..] {
+        state.messages[i].content = Some(COMPACTED.to_string());
+    }
+    on_event(PlanEvent::Compacted { before, after: conversation_tokens(&state.messages) });
+    true
+}
+
+/// The sources `text` cites, in number order; all of them if it cites none,
+/// so a plan that forgot to cite still says where its research came from.
+fn referenced<'a>(sources: &'a [Source], text: &str) -> Vec<&'a Source> {
+    let cited = cited_numbers(text);
+    if cited.is_empty() {
+        return sources.iter().collect();
+    }
+    sources.iter().filter(|s| cited.contains(&s.number)).collect()
+}
+
+/// Numbers cited as `[3]`, `[3][7]` or `[3, 7]`.
+fn cited_numbers(text: &str) -> std::collections::BTreeSet<usize> {
+    let mut numbers = std::collections::BTreeSet::new();
+    for (start, _) in text.match_indices('[') {
+        let Some(end) = text[start + 1..].find(']') else {
+            break;
+        };
+        let inside = &text[start + 1..start + 1 + end];
+        let parsed: Result<Vec<usize>, _> = inside.split(',').map(|n| n.trim().parse()).collect();
+        if let Ok(parsed) = parsed {
+            numbers.extend(parsed);
+        }
+    }
+    numbers
+}
+
+fn bibliography(sources: &[&Source]) -> String {
+    let mut section = String::from("\n\n## Sources\n\n");
+    for source in sources {
+        match &source.title {
+            Some(title) => section.push_str(&format!("- [{}] {title} - {}\n", source.number, source.url)),
+            None => section.push_str(&format!("- [{}] {}\n", source.number, source.url)),
+        }
+    }
+    section
+}
+
+/// Ask which parts of the project need their own research, based on the
+/// foundational findings. An error just means going straight to synthesis.
+async fn identify_components(llm: &dyn LlmProvider, knowledge_base: &str) -> Vec<String> {
+    let prompt = vec![
+        Message::new(
+            "system",
+            "You are **PlanBot-architect**. From the research notes below, list the key components \
+            of the project that each deserve their own research (e.g. data ingestion, storage, \
+            deployment). Reply with one short component name per line, at most six, and nothing else.",
+        ),
+        Message::new("user", knowledge_base),
+    ];
+
+    match llm.stream(prompt, None, &|_| {}).await {
+        Ok(reply) => parse_components(reply.content.as_deref().unwrap_or("")),
+        Err(e) => {
+            println!("Could not identify components, skipping to synthesis: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// One component per line, tolerating list markers and bold, dropping
+/// duplicates.
+fn parse_components(reply: &str) -> Vec<String> {
+    let mut components: Vec<String> = Vec::new();
+    for line in reply.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix(['-', '*', '•']).unwrap_or(line);
+        let line = match line.split_once(['.', ')']) {
+            Some((number, rest)) if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) => rest,
+            _ => line,
+        };
+        let name = line.trim().trim_matches('*').trim();
+        if !name.is_empty() && !components.iter().any(|c| c.eq_ignore_ascii_case(name)) {
+            components.push(name.to_string());
+        }
+    }
+    components.truncate(MAX_COMPONENTS);
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::MockLlm;
+
+    fn canned_search<'a>(query: &'a str, on_event: OnEvent<'a>) -> BoxFuture<'a, Vec<Page>> {
+        Box::pin(async move {
+            let url = format!("https://example.com/{}", query.replace(' ', "-"));
+            on_event(PlanEvent::PageFetched { url: url.clone() });
+            vec![Page { url, title: None, content: format!("canned result for {query}") }]
+        })
+    }
+
+    #[tokio::test]
+    async fn questions_then_research_then_plan() {
+        let llm = MockLlm::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/plan_pipeline.json"
+        ))
+        .unwrap();
+
+        let goal = Message::new("user", "Build a Rust web scraper for product data");
+        let questions = create_plan(&llm, std::slice::from_ref(&goal), PlanFormat::Markdown, &canned_search, &|_| {}).await;
+        assert_eq!(questions.lines().count(), 6);
+
+        let conversation = vec![
+            goal,
+            Message::new("assistant", &questions),
+            Message::new("user", "Three static shops, nightly, Postgres, our ops team."),
+        ];
+        let events = std::sync::Mutex::new(Vec::new());
+        let record = |event: PlanEvent| events.lock().unwrap().push(event);
+        let plan = create_plan(&llm, &conversation, PlanFormat::MarkdownWithDiagrams, &canned_search, &record).await;
+        assert!(plan.starts_with("## Final Technical Plan"), "{plan}");
+        assert!(plan.contains("## Timeline\n\n```mermaid\ngantt\n"), "{plan}");
+        assert!(plan.contains("```mermaid\nflowchart LR\n"), "{plan}");
+        // only the sources the plan cites, numbered as the research saw them
+        assert!(plan.ends_with(
+            "## Sources\n\n- [1] https://example.com/rust-reqwest-scraper-crate-comparison\n\
+            - [4] https://example.com/postgres-bulk-insert-from-rust-sqlx\n"
+        ), "{plan}");
+
+        let events = events.into_inner().unwrap();
+        let summary: Vec<String> = events
+            .iter()
+            .map(|e| match e {
+                PlanEvent::Stage { stage } => format!("stage {stage:?}"),
+                PlanEvent::Phase { phase } => format!("phase {phase}"),
+                PlanEvent::Components { components } => format!("components {}", components.join(", ")),
+                PlanEvent::Search { number, .. } => format!("search {number}"),
+                PlanEvent::PageFetched { .. } => "page".to_string(),
+                PlanEvent::PageFailed { .. } => "failed".to_string(),
+                PlanEvent::PageSkipped { .. } => "skipped".to_string(),
+                PlanEvent::Synthesis { .. } => "synthesis".to_string(),
+                PlanEvent::Compacted { .. } => "compacted".to_string(),
+                PlanEvent::Token { .. } => "token".to_string(),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                "stage Research",
+                "phase Foundational research", "search 1", "page", "synthesis",
+                "components Fetching and parsing, Storage",
+                "phase Component: Fetching and parsing", "search 2", "page", "search 3", "page", "synthesis",
+                "phase Component: Storage", "search 4", "page", "synthesis",
+                "phase Overall synthesis", "synthesis",
+                "stage Plan", "token", "token", "token",
+            ]
+        );
+
+        // questions; foundational search + notes; components; a search turn +
+        // notes per component; overall synthesis; the final plan; its schedule
+        let requests = llm.requests();
+        assert_eq!(requests.len(), 11);
+
+        let search_result = requests[2].last().unwrap();
+        assert_eq!(search_result.role, "tool");
+        assert_eq!(search_result.tool_call_id.as_deref(), Some("call_1"));
+        assert!(search_result
+            .content
+            .as_deref()
+            .unwrap()
+            .starts_with("[1] URL: https://example.com/rust-reqwest-scraper-crate-comparison\nContent: canned result for"));
+
+        // the architect saw the foundational notes
+        assert!(requests[3][1].content.as_deref().unwrap().contains("## Foundational research"));
+
+        // both parallel calls in the component turn got an answer, in order
+        let after_parallel = &requests[5];
+        let ids: Vec<_> = after_parallel[after_parallel.len() - 2..]
+            .iter()
+            .map(|m| m.tool_call_id.as_deref().unwrap())
+            .collect();
+        assert_eq!(ids, ["call_2", "call_3"]);
+
+        let storage_brief = requests[6].last().unwrap().content.as_deref().unwrap();
+        assert!(storage_brief.starts_with("Phase: component analysis of **Storage**"), "{storage_brief}");
+        let synthesis_brief = requests[8].last().unwrap().content.as_deref().unwrap();
+        assert!(synthesis_brief.starts_with("Phase: synthesis"), "{synthesis_brief}");
+
+        let knowledge_base = requests[9][1].content.as_deref().unwrap();
+        for expected in [
+            "--- Search #1: rust reqwest scraper crate comparison ---",
+            "## Foundational research",
+            "## Component: Fetching and parsing",
+            "--- Search #3: scraping javascript heavy shops rust ---\nSources: [3] https://example.com/scraping-javascript-heavy-shops-rust\n",
+            "## Component: Storage",
+            "--- Search #4: postgres bulk insert from rust sqlx ---",
+            "## Overall synthesis",
+            "<<FINAL_ANSWER>>",
+        ] {
+            assert!(knowledge_base.contains(expected), "missing {expected:?} in {knowledge_base}");
+        }
+
+        // the diagrams are drawn from the written plan, not the raw research
+        assert!(requests[10][1].content.as_deref().unwrap().starts_with("## Final Technical Plan"));
+    }
+
+    #[tokio::test]
+    async fn older_search_results_are_summarized_keeping_citations() {
+        let llm = MockLlm::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/compaction.json")).unwrap();
+        let mut state = ResearchState {
+            phase: ResearchPhase::Foundational,
+            components: Vec::new(),
+            messages: vec![Message::new("system", "research"), Message::new("user", "Bulk load product data")],
+            knowledge_base: String::new(),
+            search_count: 0,
+            sources: Vec::new(),
+            turns: 0,
+        };
+        for (id, query) in [("call_1", "postgres copy"), ("call_2", "sqlx batching"), ("call_3", "pgbouncer")] {
+            let call: crate::llm::ToolCall = serde_json::from_value(serde_json::json!({
+                "id": id,
+                "function": { "name": "search_web", "arguments": format!("{{\"query\": \"{query}\"}}") }
+            }))
+            .unwrap();
+            let page = Page { url: format!("https://example.com/{}", query.replace(' ', "-")), title: None, content: String::new() };
+            let number = state.cite(&page);
+            state.messages.push(Message { role: "assistant".to_string(), tool_calls: Some(vec![call.clone()]), ..Default::default() });
+            state.messages.push(Message::tool_result(&call, format!("[{number}] URL: {}\nContent: all about {query}{}", page.url, ", in detail".repeat(100))));
+        }
+
+        let events = std::sync::Mutex::new(Vec::new());
+        let record = |event: PlanEvent| events.lock().unwrap().push(event);
+        assert!(compact(&llm, &mut state, &record).await);
+
+        let contents: Vec<&str> = state.messages.iter().filter_map(|m| m.content.as_deref()).collect();
+        assert_eq!(
+            contents[2..4],
+            [
+                "Summary of earlier search results:\nCOPY beats INSERT for bulk loads [1].\n\n\
+                Also read: [2] https://example.com/sqlx-batching",
+                COMPACTED,
+            ]
+        );
+        assert!(contents[4].starts_with("[3] URL: https://example.com/pgbouncer\nContent: all about pgbouncer"));
+        assert!(matches!(events.into_inner().unwrap()[..], [PlanEvent::Compacted { before, after }] if after < before));
+
+        // the archivist saw both older results and not the latest one
+        let shown = llm.requests()[0][1].content.clone().unwrap();
+        assert!(shown.contains("all about postgres copy") && shown.contains("all about sqlx batching"), "{shown}");
+        assert!(!shown.contains("pgbouncer"), "{shown}");
+
+        // a lone summary is left alone rather than summarized again
+        assert!(!compact(&llm, &mut state, &|_| {}).await);
+    }
+
+    #[tokio::test]
+    async fn research_that_never_settles_is_moved_on_to_the_synthesis() {
+        let call = |n: usize| serde_json::json!({
+            "tool_calls": [{ "id": format!("call_{n}"), "function": { "name": "browse", "arguments": "{}" } }]
+        });
+        let mut turns: Vec<serde_json::Value> = (0..MAX_RESEARCH_TURNS).map(call).collect();
+        turns.push(serde_json::json!({ "content": "Nothing useful was found." }));
+        turns.push(serde_json::json!({ "content": "## Final Technical Plan\nStart small." }));
+        let fixture = std::env::temp_dir().join(format!("mls-gigachad-turns-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&fixture, serde_json::to_string(&turns).unwrap()).unwrap();
+        let llm = MockLlm::from_file(&fixture).unwrap();
+        std::fs::remove_file(&fixture).ok();
+
+        let conversation = vec![
+            Message::new("user", "Build a Rust web scraper"),
+            Message::new("assistant", "Which sites?"),
+            Message::new("user", "Three shops."),
+        ];
+        let phases = std::sync::Mutex::new(Vec::new());
+        let record = |event: PlanEvent| {
+            if let PlanEvent::Phase { phase } = event {
+                phases.lock().unwrap().push(phase);
+            }
+        };
+        let plan = create_plan(&llm, &conversation, PlanFormat::Markdown, &canned_search, &record).await;
+
+        assert!(plan.starts_with("## Final Technical Plan\nStart small."), "{plan}");
+        assert_eq!(phases.into_inner().unwrap(), [ResearchPhase::Foundational, ResearchPhase::Synthesis]);
+        // research turns, the synthesis, the plan; no diagrams were asked for
+        let requests = llm.requests();
+        assert_eq!(requests.len(), MAX_RESEARCH_TURNS + 2);
+        let brief = requests[MAX_RESEARCH_TURNS].last().unwrap().content.as_deref().unwrap();
+        assert!(brief.starts_with("Phase: synthesis"), "{brief}");
+    }
+
+    #[tokio::test]
+    async fn searches_asked_for_during_the_synthesis_are_refused() {
+        let turns = serde_json::json!([
+            { "tool_calls": [{ "id": "call_1", "function": { "name": "search_web", "arguments": "{\"query\": \"more\"}" } }] },
+            { "content": "Use COPY.\n<<FINAL_ANSWER>>" },
+            { "content": "## Final Technical Plan\nUse COPY." }
+        ]);
+        let fixture = std::env::temp_dir().join(format!("mls-gigachad-synthesis-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&fixture, turns.to_string()).unwrap();
+        let llm = MockLlm::from_file(&fixture).unwrap();
+        std::fs::remove_file(&fixture).ok();
+
+        let resume = ResearchState {
+            phase: ResearchPhase::Synthesis,
+            components: Vec::new(),
+            messages: vec![Message::new("system", "research"), Message::new("user", ResearchPhase::Synthesis.brief())],
+            knowledge_base: "COPY is fast.".to_string(),
+            search_count: 3,
+            sources: Vec::new(),
+            turns: 5,
+        };
+        let conversation = vec![Message::new("user", "Bulk load"), Message::new("assistant", "How much?"), Message::new("user", "Lots.")];
+        let searched = std::sync::atomic::AtomicBool::new(false);
+        let record = |event: PlanEvent| {
+            if matches!(event, PlanEvent::Search { .. }) {
+                searched.store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+        };
+        let plan =
+            run_plan(&llm, &conversation, PlanFormat::Markdown, Some(resume), &canned_search, &record, &|_| {}).await.unwrap();
+
+        assert!(plan.starts_with("## Final Technical Plan"), "{plan}");
+        assert!(!searched.into_inner());
+        let refusal = llm.requests()[1].last().unwrap().content.clone().unwrap();
+        assert!(refusal.starts_with("Searching is closed"), "{refusal}");
+    }
+
+    #[test]
+    fn cited_numbers_are_found_in_any_bracket_style() {
+        let text = "Use COPY [3][7], not INSERT [2, 3]. See [the docs].";
+        assert_eq!(cited_numbers(text).into_iter().collect::<Vec<_>>(), [2, 3, 7]);
+    }
+
+    #[test]
+    fn components_are_parsed_one_per_line() {
+        let reply = "1) Data ingestion\n\n- **3D rendering**\n2. data INGESTION\n• Deployment";
+        assert_eq!(parse_components(reply), ["Data ingestion", "3D rendering", "Deployment"]);
+    }
+}
@@ -0,0 +1,174 @@
+//! Web search backends.
+//!
+//! A [`SearchProvider`] only turns a query into result links; reading the
+//! pages behind them is shared by every backend ([`Fetcher`]). Which
+//! backends run is decided once at startup by [`from_env`].
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+mod brave;
+mod cache;
+mod document;
+mod duckduckgo;
+mod extract;
+mod fetch;
+mod local;
+mod rank;
+mod robots;
+mod searxng;
+
+pub use brave::Brave;
+pub use cache::HttpCache;
+pub use duckduckgo::DuckDuckGo;
+pub use fetch::Fetcher;
+pub use local::LocalCorpus;
+pub use rank::{best_chunks, Budget};
+pub use searxng::SearXng;
+
+use crate::llm::Error;
+
+/// Provider handle shared between the server, the jobs and the CLI.
+pub type SharedSearch = Arc<dyn SearchProvider>;
+
+/// One link from a results page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub url: String,
+    pub title: Option<String>,
+    /// The engine's short excerpt, read when the page itself can't be.
+    pub snippet: Option<String>,
+    /// The text itself, when the backend already has it (a local index);
+    /// such results aren't fetched.
+    pub content: Option<String>,
+}
+
+#[async_trait]
+pub trait SearchProvider: Send + Sync {
+    /// Short name used in logs, e.g. `searxng`.
+    fn name(&self) -> &str;
+
+    /// The results for `query`, best first.
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Error>;
+}
+
+/// Several providers tried in order: the first one that answers with any
+/// results wins, so a backend that blocks us doesn't end the research.
+pub struct Fallback {
+    providers: Vec<SharedSearch>,
+    name: String,
+}
+
+impl Fallback {
+    pub fn new(providers: Vec<SharedSearch>) -> Self {
+        let name = providers.iter().map(|p| p.name()).collect::<Vec<_>>().join(", then ");
+        Self { providers, name }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for Fallback {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Error> {
+        let mut errors = Vec::new();
+        for provider in &self.providers {
+            match provider.search(query).await {
+                Ok(results) if !results.is_empty() => return Ok(results),
+                Ok(_) => errors.push(format!("{}: no results", provider.name())),
+                Err(e) => errors.push(format!("{}: {e}", provider.name())),
+            }
+        }
+        Err(errors.join("; ").into())
+    }
+}
+
+/// Build the providers named by `SEARCH_PROVIDER` (default `duckduckgo`), a
+/// comma-separated list tried in order, e.g. `searxng,duckduckgo`.
+///
+/// * `duckduckgo` - scrapes `html.duckduckgo.com`; needs nothing.
+/// * `searxng`    - a SearXNG instance's JSON API; needs `SEARXNG_URL`.
+/// * `brave`      - the Brave Search API; needs `BRAVE_API_KEY`.
+/// * `local`      - a full-text index over `LOCAL_CORPUS_DIR`, no network needed.
+pub fn from_env() -> Result<SharedSearch, Error> {
+    let names = std::env::var("SEARCH_PROVIDER").unwrap_or_else(|_| "duckduckgo".to_string());
+
+    let mut providers: Vec<SharedSearch> = Vec::new();
+    for name in names.split(',').map(|n| n.trim().to_lowercase()).filter(|n| !n.is_empty()) {
+        let provider: SharedSearch = match name.as_str() {
+            "duckduckgo" | "ddg" => Arc::new(DuckDuckGo::new()?),
+            "searxng" => Arc::new(SearXng::from_env()?),
+            "brave" => Arc::new(Brave::from_env()?),
+            "local" => Arc::new(LocalCorpus::from_env()?),
+            other => {
+                return Err(
+                    format!("unknown SEARCH_PROVIDER '{other}' (expected duckduckgo, searxng, brave or local)").into()
+                );
+            }
+        };
+        providers.push(provider);
+    }
+
+    match providers.len() {
+        0 => Err("SEARCH_PROVIDER names no provider".into()),
+        1 => Ok(providers.remove(0)),
+        _ => Ok(Arc::new(Fallback::new(providers))),
+    }
+}
+
+/// Per request, for search APIs and result pages alike.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sent unless `CRAWLER_USER_AGENT` says otherwise.
+const DEFAULT_USER_AGENT: &str = concat!("mls-gigachad/", env!("CARGO_PKG_VERSION"), " (technical plan research bot)");
+
+/// Who we say we are to search APIs and the sites we read: honestly a bot,
+/// so sites can tell us apart and their robots.txt can address us.
+fn user_agent() -> String {
+    std::env::var("CRAWLER_USER_AGENT")
+        .ok()
+        .filter(|agent| !agent.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string())
+}
+
+fn http_client() -> Result<reqwest::Client, Error> {
+    Ok(reqwest::Client::builder().timeout(REQUEST_TIMEOUT).user_agent(user_agent()).build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Canned(&'static str, Result<Vec<SearchResult>, &'static str>);
+
+    #[async_trait]
+    impl SearchProvider for Canned {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        async fn search(&self, _query: &str) -> Result<Vec<SearchResult>, Error> {
+            self.1.clone().map_err(Into::into)
+        }
+    }
+
+    #[tokio::test]
+    async fn fallback_moves_on_from_blocked_and_empty_backends() {
+        let result = SearchResult { url: "https://docs.rs/sqlx".to_string(), title: None, snippet: None, content: None };
+        let search = Fallback::new(vec![
+            Arc::new(Canned("duckduckgo", Err("403 Forbidden"))),
+            Arc::new(Canned("searxng", Ok(vec![]))),
+            Arc::new(Canned("brave", Ok(vec![result.clone()]))),
+        ]);
+        assert_eq!(search.name(), "duckduckgo, then searxng, then brave");
+        assert_eq!(search.search("sqlx").await.unwrap(), [result]);
+
+        let blocked = Fallback::new(vec![Arc::new(Canned("duckduckgo", Err("403 Forbidden")))]);
+        let error = blocked.search("sqlx").await.unwrap_err().to_string();
+        assert_eq!(error, "duckduckgo: 403 Forbidden");
+    }
+}
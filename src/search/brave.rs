@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use scraper::Html;
+use serde_json::Value;
+
+use super::{http_client, SearchProvider, SearchResult};
+use crate::llm::{required_env, Error};
+
+const BRAVE_SEARCH_URL: &str = "https://api.search.brave.com/res/v1/web/search";
+
+/// The Brave Search API (`/res/v1/web/search`).
+pub struct Brave {
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl Brave {
+    /// `BRAVE_API_KEY`, a subscription token from the Brave Search API dashboard.
+    pub fn from_env() -> Result<Self, Error> {
+        Ok(Self {
+            api_key: required_env("BRAVE_API_KEY")?,
+            client: http_client()?,
+        })
+    }
+}
+
+#[async_trait]
+impl SearchProvider for Brave {
+    fn name(&self) -> &str {
+        "brave"
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Error> {
+        let response = self
+            .client
+            .get(BRAVE_SEARCH_URL)
+            .query(&[("q", query)])
+            .header("Accept", "application/json")
+            .header("X-Subscription-Token", &self.api_key)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(format!("brave request failed with {status}: {error_text}").into());
+        }
+        Ok(parse(&response.json().await?))
+    }
+}
+
+fn parse(body: &Value) -> Vec<SearchResult> {
+    let Some(results) = body["web"]["results"].as_array() else {
+        return Vec::new();
+    };
+    results
+        .iter()
+        .filter_map(|result| {
+            Some(SearchResult {
+                url: result["url"].as_str()?.to_string(),
+                title: result["title"].as_str().map(str::to_string),
+                snippet: result["description"].as_str().map(strip_tags),
+                content: None,
+            })
+        })
+        .collect()
+}
+
+/// Brave marks the query words in descriptions with `<strong>`.
+fn strip_tags(html: &str) -> String {
+    Html::parse_fragment(html).root_element().text().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_brave_results() {
+        let body = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/brave_results.json"))
+            .unwrap();
+        let results = parse(&serde_json::from_str(&body).unwrap());
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].url, "https://github.com/launchbadge/sqlx/issues/1216");
+        assert_eq!(results[1].title.as_deref(), Some("Support for COPY · Issue #1216 · launchbadge/sqlx"));
+        assert_eq!(
+            results[1].snippet.as_deref(),
+            Some("Bulk inserts with COPY are an order of magnitude faster than batched INSERTs.")
+        );
+    }
+
+    #[test]
+    fn queries_without_web_results_are_empty() {
+        let body = serde_json::json!({ "type": "search", "query": { "original": "zzzz" } });
+        assert!(parse(&body).is_empty());
+    }
+}
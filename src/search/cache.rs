@@ -0,0 +1,358 @@
+//! On-disk cache of fetched pages, so rerunning a plan doesn't download the
+//! same documentation again.
+//!
+//! Bodies are stored once per content hash (`<dir>/ab/abcdef...`); an SQLite
+//! index maps each URL to its body, validators and expiry. Freshness follows
+//! the server's `Cache-Control` (capped at the configured TTL), stale entries
+//! with an `ETag` or `Last-Modified` are revalidated instead of refetched, and
+//! the least recently used entries are evicted once the bodies pass the size
+//! cap.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::{HeaderMap, CACHE_CONTROL, CONTENT_TYPE, ETAG, LAST_MODIFIED};
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+use crate::llm::Error;
+
+const DEFAULT_CACHE_DIR: &str = "http-cache";
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const DEFAULT_MAX_MB: u64 = 200;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS entries (
+        url           TEXT PRIMARY KEY,
+        body          TEXT NOT NULL,
+        size          INTEGER NOT NULL,
+        content_type  TEXT,
+        etag          TEXT,
+        last_modified TEXT,
+        fetched_at    INTEGER NOT NULL,
+        expires_at    INTEGER NOT NULL,
+        last_used     INTEGER NOT NULL
+    );
+";
+
+pub struct HttpCache {
+    dir: PathBuf,
+    conn: Mutex<Connection>,
+    ttl: Duration,
+    max_bytes: u64,
+}
+
+/// A cached response, fresh or due for revalidation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedPage {
+    pub body: Vec<u8>,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub fresh: bool,
+}
+
+/// One row of `cache inspect`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub url: String,
+    pub size: u64,
+    pub fetched_at: u64,
+    pub expires_at: u64,
+}
+
+impl HttpCache {
+    /// `HTTP_CACHE_DIR` (default `http-cache`), `HTTP_CACHE_TTL` in seconds
+    /// (default a day; `0` turns the cache off) and `HTTP_CACHE_MAX_MB`
+    /// (default 200).
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        let ttl = match std::env::var("HTTP_CACHE_TTL") {
+            Ok(secs) => {
+                let secs = secs.trim().parse().map_err(|_| format!("HTTP_CACHE_TTL={secs} is not a number of seconds"))?;
+                Duration::from_secs(secs)
+            }
+            Err(_) => DEFAULT_TTL,
+        };
+        if ttl.is_zero() {
+            return Ok(None);
+        }
+        let max_mb: u64 = match std::env::var("HTTP_CACHE_MAX_MB") {
+            Ok(mb) => mb.trim().parse().map_err(|_| format!("HTTP_CACHE_MAX_MB={mb} is not a number"))?,
+            Err(_) => DEFAULT_MAX_MB,
+        };
+        let dir = std::env::var("HTTP_CACHE_DIR").unwrap_or_else(|_| DEFAULT_CACHE_DIR.to_string());
+        Ok(Some(Self::open(dir, ttl, max_mb.saturating_mul(1024 * 1024))?))
+    }
+
+    pub fn open(dir: impl AsRef<Path>, ttl: Duration, max_bytes: u64) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir).map_err(|e| format!("cannot create cache directory {}: {e}", dir.display()))?;
+        let conn = Connection::open(dir.join("index.db"))?;
+        conn.execute_batch(SCHEMA)?;
+
+        Ok(Self { dir, conn: Mutex::new(conn), ttl, max_bytes })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The cached response for `url`, if there is one and its body is intact.
+    pub fn lookup(&self, url: &str) -> Option<CachedPage> {
+        let conn = self.conn.lock().unwrap();
+        let row = conn
+            .query_row(
+                "SELECT body, content_type, etag, last_modified, expires_at FROM entries WHERE url = ?1",
+                [url],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, u64>(4)?,
+                    ))
+                },
+            )
+            .optional()
+            .unwrap_or_else(|e| {
+                println!("Cache lookup for {url} failed: {e}");
+                None
+            })?;
+        let (hash, content_type, etag, last_modified, expires_at) = row;
+
+        let body = std::fs::read(self.body_path(&hash)).ok()?;
+        let _ = conn.execute("UPDATE entries SET last_used = ?2 WHERE url = ?1", params![url, now()]);
+        Some(CachedPage { body, content_type, etag, last_modified, fresh: now() < expires_at })
+    }
+
+    /// Keep a `200` response, unless the server said not to.
+    pub fn store(&self, url: &str, headers: &HeaderMap, body: &[u8]) -> Result<(), Error> {
+        let Some(lifetime) = lifetime(headers, self.ttl) else {
+            return Ok(());
+        };
+        let hash = hex(&Sha256::digest(body));
+        let path = self.body_path(&hash);
+        if !path.exists() {
+            std::fs::create_dir_all(path.parent().unwrap_or(&self.dir))?;
+            let partial = path.with_extension("partial");
+            std::fs::write(&partial, body)?;
+            std::fs::rename(&partial, &path)?;
+        }
+
+        let now = now();
+        {
+            let conn = self.conn.lock().unwrap();
+            let replaced: Option<String> =
+                conn.query_row("SELECT body FROM entries WHERE url = ?1", [url], |row| row.get(0)).optional()?;
+            conn.execute(
+                "INSERT OR REPLACE INTO entries
+                 (url, body, size, content_type, etag, last_modified, fetched_at, expires_at, last_used)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?7)",
+                params![
+                    url,
+                    hash,
+                    body.len() as u64,
+                    header(headers, CONTENT_TYPE.as_str()),
+                    header(headers, ETAG.as_str()),
+                    header(headers, LAST_MODIFIED.as_str()),
+                    now,
+                    now + lifetime.as_secs(),
+                ],
+            )?;
+            if let Some(old) = replaced.filter(|old| *old != hash) {
+                self.remove_unreferenced(&conn, &old)?;
+            }
+        }
+        self.evict()
+    }
+
+    /// The server answered `304 Not Modified`: the entry is fresh again.
+    pub fn revalidated(&self, url: &str, headers: &HeaderMap) -> Result<(), Error> {
+        let lifetime = lifetime(headers, self.ttl).unwrap_or_default();
+        let now = now();
+        self.conn.lock().unwrap().execute(
+            "UPDATE entries SET expires_at = ?2, last_used = ?3 WHERE url = ?1",
+            params![url, now + lifetime.as_secs(), now],
+        )?;
+        Ok(())
+    }
+
+    /// Every entry, most recently fetched first.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement =
+            conn.prepare("SELECT url, size, fetched_at, expires_at FROM entries ORDER BY fetched_at DESC, url")?;
+        let entries = statement
+            .query_map([], |row| {
+                Ok(CacheEntry { url: row.get(0)?, size: row.get(1)?, fetched_at: row.get(2)?, expires_at: row.get(3)? })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(entries)
+    }
+
+    /// Bytes on disk; a body shared by several URLs counts once.
+    pub fn total_size(&self) -> Result<u64, Error> {
+        let conn = self.conn.lock().unwrap();
+        Ok(Self::stored_bytes(&conn)?)
+    }
+
+    /// Drop every entry, or only the expired ones. Returns how many went.
+    pub fn purge(&self, expired_only: bool) -> Result<usize, Error> {
+        let conn = self.conn.lock().unwrap();
+        let urls: Vec<(String, String)> = {
+            let mut statement = conn.prepare("SELECT url, body FROM entries WHERE ?1 = 0 OR expires_at <= ?2")?;
+            statement
+                .query_map(params![expired_only, now()], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?
+        };
+        for (url, hash) in &urls {
+            conn.execute("DELETE FROM entries WHERE url = ?1", [url])?;
+            self.remove_unreferenced(&conn, hash)?;
+        }
+        Ok(urls.len())
+    }
+
+    /// Drop least recently used entries until the bodies fit the size cap.
+    fn evict(&self) -> Result<(), Error> {
+        let conn = self.conn.lock().unwrap();
+        while Self::stored_bytes(&conn)? > self.max_bytes {
+            let Some((url, hash)): Option<(String, String)> = conn
+                .query_row("SELECT url, body FROM entries ORDER BY last_used, fetched_at LIMIT 1", [], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .optional()?
+            else {
+                break;
+            };
+            conn.execute("DELETE FROM entries WHERE url = ?1", [&url])?;
+            self.remove_unreferenced(&conn, &hash)?;
+        }
+        Ok(())
+    }
+
+    fn stored_bytes(conn: &Connection) -> rusqlite::Result<u64> {
+        conn.query_row(
+            "SELECT COALESCE(SUM(size), 0) FROM (SELECT MAX(size) AS size FROM entries GROUP BY body)",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    fn remove_unreferenced(&self, conn: &Connection, hash: &str) -> Result<(), Error> {
+        let users: usize = conn.query_row("SELECT COUNT(*) FROM entries WHERE body = ?1", [hash], |row| row.get(0))?;
+        if users == 0 {
+            std::fs::remove_file(self.body_path(hash)).ok();
+        }
+        Ok(())
+    }
+
+    fn body_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(hash)
+    }
+}
+
+/// How long a response stays fresh: its `max-age` capped at `ttl`, `ttl`
+/// without one, zero for `no-cache`, and `None` for `no-store`.
+fn lifetime(headers: &HeaderMap, ttl: Duration) -> Option<Duration> {
+    let cache_control = header(headers, CACHE_CONTROL.as_str()).unwrap_or_default().to_lowercase();
+    let directives: Vec<&str> = cache_control.split(',').map(str::trim).collect();
+
+    if directives.contains(&"no-store") {
+        return None;
+    }
+    if directives.contains(&"no-cache") {
+        return Some(Duration::ZERO);
+    }
+    let max_age = directives.iter().find_map(|d| d.strip_prefix("max-age=")?.parse().ok());
+    Some(max_age.map_or(ttl, |secs| Duration::from_secs(secs).min(ttl)))
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get(name)?.to_str().ok().map(str::to_string)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(cache_control: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if !cache_control.is_empty() {
+            headers.insert(CACHE_CONTROL, HeaderValue::from_str(cache_control).unwrap());
+        }
+        headers
+    }
+
+    fn temp_cache(max_bytes: u64) -> HttpCache {
+        let dir = std::env::temp_dir().join(format!("mls-gigachad-cache-{}", uuid::Uuid::new_v4()));
+        HttpCache::open(dir, Duration::from_secs(3600), max_bytes).unwrap()
+    }
+
+    #[test]
+    fn cache_control_sets_the_lifetime() {
+        let ttl = Duration::from_secs(3600);
+        assert_eq!(lifetime(&headers(""), ttl), Some(ttl));
+        assert_eq!(lifetime(&headers("public, max-age=60"), ttl), Some(Duration::from_secs(60)));
+        assert_eq!(lifetime(&headers("max-age=31536000, immutable"), ttl), Some(ttl));
+        assert_eq!(lifetime(&headers("no-cache"), ttl), Some(Duration::ZERO));
+        assert_eq!(lifetime(&headers("private, No-Store"), ttl), None);
+    }
+
+    #[test]
+    fn identical_bodies_are_stored_once() {
+        let cache = temp_cache(1024 * 1024);
+        cache.store("https://a.example/page", &headers(""), b"same body").unwrap();
+        cache.store("https://b.example/mirror", &headers(""), b"same body").unwrap();
+        cache.store("https://c.example/secret", &headers("no-store"), b"secret").unwrap();
+
+        assert_eq!(cache.entries().unwrap().len(), 2);
+        assert_eq!(cache.total_size().unwrap(), 9);
+        let page = cache.lookup("https://b.example/mirror").unwrap();
+        assert_eq!(page.body, b"same body");
+        assert!(page.fresh);
+        assert_eq!(cache.lookup("https://c.example/secret"), None);
+
+        std::fs::remove_dir_all(cache.dir()).ok();
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted_past_the_size_cap() {
+        let cache = temp_cache(25);
+        cache.store("https://example.com/1", &headers(""), b"first page").unwrap();
+        cache.store("https://example.com/2", &headers("max-age=0"), b"second page").unwrap();
+        cache.conn.lock().unwrap().execute("UPDATE entries SET last_used = 0 WHERE url LIKE '%/1'", []).unwrap();
+        cache.store("https://example.com/3", &headers(""), b"third page").unwrap();
+
+        let urls: Vec<String> = cache.entries().unwrap().into_iter().map(|e| e.url).collect();
+        assert!(!urls.contains(&"https://example.com/1".to_string()), "{urls:?}");
+        let files = walk(cache.dir());
+        assert_eq!(files, 2, "the evicted body was left on disk");
+
+        assert!(!cache.lookup("https://example.com/2").unwrap().fresh);
+        assert_eq!(cache.purge(true).unwrap(), 1);
+        assert_eq!(cache.purge(false).unwrap(), 1);
+        assert_eq!(walk(cache.dir()), 0);
+
+        std::fs::remove_dir_all(cache.dir()).ok();
+    }
+
+    /// Body files under `dir`, leaving out the index.
+    fn walk(dir: &Path) -> usize {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .map(|path| if path.is_dir() { walk(&path) } else { usize::from(!path.to_string_lossy().contains("index.db")) })
+            .sum()
+    }
+}
@@ -0,0 +1,162 @@
+//! Text from whatever a result link points at, chosen by its Content-Type.
+//!
+//! HTML goes through [`readable`]; PDFs, plain text, markdown and JSON have
+//! readers of their own. Images, archives and other binaries aren't read at
+//! all: [`read`] says what they are instead, so the researcher knows what it
+//! is missing.
+
+use super::extract::{readable, Readable};
+
+/// Leading bytes looked at when the server didn't say what it sent.
+pub const SNIFF_LEN: usize = 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Kind {
+    Html,
+    Pdf,
+    Markdown,
+    Text,
+    Json,
+    /// Not text; the description says what it is.
+    Binary(String),
+}
+
+/// The document's title (if it has one) and text as markdown, or why it
+/// can't be read.
+pub fn read(content_type: Option<&str>, body: &[u8]) -> Result<Readable, String> {
+    let text = || String::from_utf8_lossy(body).into_owned();
+    match kind(content_type, body) {
+        Kind::Html => Ok(readable(&text())),
+        Kind::Pdf => Ok(Readable { title: None, markdown: pdf_text(body)? }),
+        Kind::Markdown | Kind::Text => {
+            let text = text();
+            let title = text.lines().find_map(|l| l.strip_prefix("# ")).map(|h| h.trim().to_string());
+            Ok(Readable { title, markdown: text })
+        }
+        Kind::Json => {
+            let markdown = match serde_json::from_slice::<serde_json::Value>(body) {
+                Ok(value) => format!("```json\n{}\n```", serde_json::to_string_pretty(&value).unwrap_or_default()),
+                Err(_) => text(),
+            };
+            Ok(Readable { title: None, markdown })
+        }
+        Kind::Binary(what) => Err(format!("{what} can't be read as text")),
+    }
+}
+
+/// Whether the body is an image, archive or other file [`read`] would only
+/// describe. With no bytes to look at, this goes by `content_type` alone.
+pub fn is_binary(content_type: Option<&str>, body: &[u8]) -> bool {
+    matches!(kind(content_type, body), Kind::Binary(_))
+}
+
+/// Whether `content_type` is an image, audio, video or font, which no
+/// document is ever served as.
+pub fn is_media(content_type: Option<&str>) -> bool {
+    let essence = content_type.and_then(|t| t.split(';').next()).unwrap_or_default().trim().to_lowercase();
+    ["image/", "audio/", "video/", "font/"].iter().any(|media| essence.starts_with(media))
+}
+
+/// `pdf-extract` panics on some malformed files rather than returning an error.
+fn pdf_text(bytes: &[u8]) -> Result<String, String> {
+    match std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes)) {
+        Ok(Ok(text)) => Ok(text),
+        Ok(Err(e)) => Err(format!("unreadable PDF: {e}")),
+        Err(_) => Err("unreadable PDF".to_string()),
+    }
+}
+
+fn kind(content_type: Option<&str>, body: &[u8]) -> Kind {
+    // Servers label PDFs as all sorts of things; the file knows better
+    if body.starts_with(b"%PDF-") {
+        return Kind::Pdf;
+    }
+    let essence = content_type
+        .and_then(|t| t.split(';').next())
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty());
+    let Some(essence) = essence else {
+        return sniff(body);
+    };
+    match essence.as_str() {
+        "text/html" | "application/xhtml+xml" => Kind::Html,
+        "application/pdf" | "application/x-pdf" => Kind::Pdf,
+        "text/markdown" | "text/x-markdown" => Kind::Markdown,
+        "application/json" => Kind::Json,
+        "application/octet-stream" | "binary/octet-stream" => sniff(body),
+        t if t.ends_with("+json") => Kind::Json,
+        t if t.starts_with("text/") || t == "application/xml" || t.ends_with("+xml") => Kind::Text,
+        t => Kind::Binary(describe(t)),
+    }
+}
+
+/// Guess from the bytes: NULs mean binary, a doctype or `<html>` means HTML,
+/// anything else is read as plain text.
+fn sniff(body: &[u8]) -> Kind {
+    let head = &body[..body.len().min(SNIFF_LEN)];
+    if head.contains(&0) {
+        return Kind::Binary("an unlabelled binary file".to_string());
+    }
+    let head = String::from_utf8_lossy(head).to_lowercase();
+    if head.contains("<!doctype html") || head.contains("<html") {
+        Kind::Html
+    } else {
+        Kind::Text
+    }
+}
+
+fn describe(content_type: &str) -> String {
+    let what = match content_type.split('/').next().unwrap_or_default() {
+        "image" => "an image",
+        "audio" => "an audio file",
+        "video" => "a video",
+        "font" => "a font",
+        _ => "a binary file",
+    };
+    format!("{what} ({content_type})")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        std::fs::read(format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))).unwrap()
+    }
+
+    #[test]
+    fn documents_are_read_by_their_content_type() {
+        let html = read(Some("text/html; charset=utf-8"), b"<html><head><title>Docs</title></head><body><p>Hi</p></body></html>");
+        assert_eq!(html, Ok(Readable { title: Some("Docs".to_string()), markdown: "Hi".to_string() }));
+
+        let markdown = read(Some("text/markdown"), b"Intro\n\n# COPY in sqlx\n\nUse copy_in_raw.").unwrap();
+        assert_eq!(markdown.title.as_deref(), Some("COPY in sqlx"));
+        assert_eq!(markdown.markdown, "Intro\n\n# COPY in sqlx\n\nUse copy_in_raw.");
+
+        let json = read(Some("application/vnd.api+json"), br#"{"rows":[1,2]}"#).unwrap();
+        assert_eq!(json.markdown, "```json\n{\n  \"rows\": [\n    1,\n    2\n  ]\n}\n```");
+
+        // not actually JSON: still worth reading as text
+        assert_eq!(read(Some("application/json"), b"rows: 1, 2").unwrap().markdown, "rows: 1, 2");
+        assert_eq!(read(Some("text/csv"), b"id,name\n1,widget").unwrap().markdown, "id,name\n1,widget");
+    }
+
+    #[test]
+    fn pdfs_are_read_whatever_they_are_labelled() {
+        let pdf = fixture("corpus/copy-benchmarks.pdf");
+        for content_type in [Some("application/pdf"), Some("application/octet-stream"), None] {
+            let text = read(content_type, &pdf).unwrap().markdown;
+            assert!(text.contains("4.1 seconds"), "{content_type:?}: {text}");
+        }
+        assert!(read(Some("application/pdf"), b"%PDF-1.7 truncated").unwrap_err().starts_with("unreadable PDF"));
+    }
+
+    #[test]
+    fn binaries_are_described_not_read() {
+        let png = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', 0, 0];
+        assert_eq!(read(Some("image/png"), &png), Err("an image (image/png) can't be read as text".to_string()));
+        assert_eq!(read(Some("application/zip"), b"PK\x03\x04"), Err("a binary file (application/zip) can't be read as text".to_string()));
+        assert_eq!(read(None, &png), Err("an unlabelled binary file can't be read as text".to_string()));
+        assert_eq!(read(None, b"<!DOCTYPE html><p>Unlabelled</p>").unwrap().markdown, "Unlabelled");
+    }
+}
@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+
+use super::{http_client, SearchProvider, SearchResult};
+use crate::llm::Error;
+
+const DUCKDUCKGO_HTML_URL: &str = "https://html.duckduckgo.com/html/";
+
+/// Scrapes DuckDuckGo's JavaScript-free results page. Needs no key, but
+/// breaks whenever the markup changes and gets rate-limited under load.
+pub struct DuckDuckGo {
+    client: reqwest::Client,
+}
+
+impl DuckDuckGo {
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self { client: http_client()? })
+    }
+}
+
+#[async_trait]
+impl SearchProvider for DuckDuckGo {
+    fn name(&self) -> &str {
+        "duckduckgo"
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Error> {
+        let response = self.client.get(search_url(query)?).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("duckduckgo answered {status}").into());
+        }
+        let html = response.text().await?;
+        // Rate-limited clients get a bot challenge instead of results
+        if html.contains("anomaly-modal") {
+            return Err("duckduckgo is rate-limiting us (bot challenge)".into());
+        }
+        Ok(parse(&html))
+    }
+}
+
+/// The results page for `query`, percent-encoded so `&`, `#` and `+` survive.
+fn search_url(query: &str) -> Result<Url, Error> {
+    Ok(Url::parse_with_params(DUCKDUCKGO_HTML_URL, &[("q", query)])?)
+}
+
+/// Organic results with their titles and snippets; ads are left out.
+fn parse(html: &str) -> Vec<SearchResult> {
+    let document = Html::parse_document(html);
+    let result = Selector::parse(".result").unwrap();
+    let link = Selector::parse("a.result__a").unwrap();
+    let snippet = Selector::parse(".result__snippet").unwrap();
+
+    let mut results = Vec::new();
+    for element in document.select(&result) {
+        if element.value().classes().any(|c| c == "result--ad") {
+            continue;
+        }
+        let Some(anchor) = element.select(&link).next() else {
+            continue;
+        };
+        let Some(url) = anchor.value().attr("href").and_then(target_url) else {
+            continue;
+        };
+        results.push(SearchResult {
+            url,
+            title: text_of(anchor),
+            snippet: element.select(&snippet).next().and_then(text_of),
+            content: None,
+        });
+    }
+    results
+}
+
+/// Result links go through DuckDuckGo's redirector,
+/// `//duckduckgo.com/l/?uddg=<target>&rut=...`; this is the target, if it
+/// is an `http(s)` page.
+fn target_url(href: &str) -> Option<String> {
+    let absolute = if href.starts_with("//") { format!("https:{href}") } else { href.to_string() };
+    let url = Url::parse(&absolute).ok()?;
+
+    let target = if url.host_str().is_some_and(|h| h == "duckduckgo.com" || h.ends_with(".duckduckgo.com")) {
+        if url.path() != "/l/" {
+            return None; // ads and internal links
+        }
+        let (_, target) = url.query_pairs().find(|(key, _)| key == "uddg")?;
+        Url::parse(&target).ok()?
+    } else {
+        url
+    };
+    // Only web pages: no javascript:, file: or data: targets
+    matches!(target.scheme(), "http" | "https").then(|| target.to_string())
+}
+
+fn text_of(element: ElementRef) -> Option<String> {
+    let text = element.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_are_percent_encoded() {
+        let url = search_url("c++ futures & tokio #select").unwrap();
+        assert_eq!(url.as_str(), "https://html.duckduckgo.com/html/?q=c%2B%2B+futures+%26+tokio+%23select");
+    }
+
+    #[test]
+    fn recorded_duckduckgo_results() {
+        let html = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/duckduckgo_results.html"))
+            .unwrap();
+        let results = parse(&html);
+
+        let urls: Vec<&str> = results.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://docs.rs/sqlx/latest/sqlx/postgres/struct.PgCopyIn.html",
+                "https://www.postgresql.org/docs/current/sql-copy.html?highlight=copy&lang=en#SQL-COPY-PARAMS",
+                "https://github.com/launchbadge/sqlx/issues/1216",
+            ]
+        );
+        assert_eq!(results[0].title.as_deref(), Some("PgCopyIn in sqlx::postgres - Rust"));
+        assert_eq!(
+            results[0].snippet.as_deref(),
+            Some("A connection in streaming COPY FROM STDIN mode, created by PgConnection::copy_in_raw.")
+        );
+        assert_eq!(results[2].snippet, None);
+    }
+
+    #[test]
+    fn redirect_links_are_decoded() {
+        assert_eq!(
+            target_url("//duckduckgo.com/l/?uddg=https%3A%2F%2Fexample.com%2Fa%3Fb%3D1%26c%3D2&rut=f00").as_deref(),
+            Some("https://example.com/a?b=1&c=2")
+        );
+        assert_eq!(target_url("https://example.com/direct").as_deref(), Some("https://example.com/direct"));
+        assert_eq!(target_url("https://duckduckgo.com/y.js?ad_domain=example.com"), None);
+        assert_eq!(target_url("javascript:void(0)"), None);
+        assert_eq!(target_url("//duckduckgo.com/l/?uddg=javascript%3Aalert(1)&rut=f00"), None);
+        assert_eq!(target_url("//duckduckgo.com/l/?uddg=file%3A%2F%2F%2Fetc%2Fpasswd&rut=f00"), None);
+        // a look-alike host is an ordinary link, not a redirect to follow
+        assert_eq!(
+            target_url("https://evilduckduckgo.com/l/?uddg=https%3A%2F%2Fexample.com%2F").as_deref(),
+            Some("https://evilduckduckgo.com/l/?uddg=https%3A%2F%2Fexample.com%2F")
+        );
+    }
+}
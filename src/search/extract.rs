@@ -0,0 +1,340 @@
+//! The readable part of an HTML page as markdown.
+//!
+//! A small take on Readability: boilerplate elements (navigation, sidebars,
+//! footers, cookie banners, scripts) are ignored, every paragraph scores its
+//! parent and grandparent, and the best-scoring block, discounted by how much
+//! of it is link text, is taken as the content. Headings, lists and code
+//! blocks survive as markdown so the model still sees the page's structure.
+
+use std::collections::HashMap;
+
+use ego_tree::{NodeId, NodeRef};
+use scraper::{ElementRef, Html, Node, Selector};
+
+/// Elements that are never content.
+const SKIPPED_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "nav", "aside", "footer", "header", "form", "button", "iframe",
+    "svg", "canvas", "select", "dialog",
+];
+
+/// `class`/`id` words of boilerplate blocks.
+const NEGATIVE: &[&str] = &[
+    "nav", "menu", "sidebar", "footer", "header", "cookie", "consent", "banner", "breadcrumb", "comment", "share",
+    "social", "related", "advert", "promo", "popup", "modal", "newsletter", "subscribe", "skip",
+];
+
+/// `class`/`id` words of content blocks.
+const POSITIVE: &[&str] = &["article", "content", "main", "post", "entry", "body", "text", "docs", "markdown", "prose"];
+
+/// Paragraphs shorter than this don't vote for a content block.
+const MIN_PARAGRAPH_LEN: usize = 25;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Readable {
+    pub title: Option<String>,
+    pub markdown: String,
+}
+
+/// The page's `<title>` and its main content as markdown.
+pub fn readable(html: &str) -> Readable {
+    let document = Html::parse_document(html);
+    let title = document
+        .select(&Selector::parse("title").unwrap())
+        .next()
+        .map(|t| collapse_whitespace(&t.text().collect::<String>()).trim().to_string())
+        .filter(|t| !t.is_empty());
+
+    let Some(body) = document.select(&Selector::parse("body").unwrap()).next() else {
+        return Readable { title, markdown: String::new() };
+    };
+    let content = best_candidate(body).unwrap_or(body);
+
+    let mut markdown = String::new();
+    render_children(*content, &mut markdown, 0);
+    Readable { title, markdown: tidy(&markdown) }
+}
+
+fn is_skipped(element: ElementRef) -> bool {
+    let value = element.value();
+    if SKIPPED_TAGS.contains(&value.name()) || value.attr("hidden").is_some() {
+        return true;
+    }
+    if value.attr("aria-hidden") == Some("true") || value.attr("role").is_some_and(|r| r == "navigation") {
+        return true;
+    }
+    // <main> and <article> are content whatever their class says
+    !matches!(value.name(), "main" | "article" | "body") && class_weight(element) < 0
+}
+
+/// -25 for a boilerplate-looking `class`/`id`, +25 for a content-looking one.
+fn class_weight(element: ElementRef) -> i32 {
+    let value = element.value();
+    let names = format!("{} {}", value.attr("class").unwrap_or_default(), value.attr("id").unwrap_or_default())
+        .to_lowercase();
+    let words: Vec<&str> = names.split(|c: char| !c.is_ascii_alphanumeric()).filter(|w| !w.is_empty()).collect();
+
+    let mut weight = 0;
+    if words.iter().any(|w| NEGATIVE.contains(w)) {
+        weight -= 25;
+    }
+    if words.iter().any(|w| POSITIVE.contains(w)) {
+        weight += 25;
+    }
+    weight
+}
+
+/// Whether `element` or anything above it (up to `<body>`) is boilerplate.
+fn in_skipped(element: ElementRef) -> bool {
+    std::iter::once(*element)
+        .chain(element.ancestors())
+        .filter_map(ElementRef::wrap)
+        .take_while(|e| e.value().name() != "body")
+        .any(is_skipped)
+}
+
+fn best_candidate(body: ElementRef) -> Option<ElementRef> {
+    let mut scores: HashMap<NodeId, f64> = HashMap::new();
+    for paragraph in body.select(&Selector::parse("p, pre, td, li").unwrap()) {
+        if in_skipped(paragraph) {
+            continue;
+        }
+        let text = visible_text(paragraph);
+        let length = text.trim().chars().count();
+        if length < MIN_PARAGRAPH_LEN {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (length as f64 / 100.0).min(3.0);
+
+        let mut ancestors = paragraph.ancestors().filter_map(ElementRef::wrap);
+        if let Some(parent) = ancestors.next() {
+            *scores.entry(parent.id()).or_default() += score;
+        }
+        if let Some(grandparent) = ancestors.next() {
+            *scores.entry(grandparent.id()).or_default() += score / 2.0;
+        }
+    }
+
+    scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let element = ElementRef::wrap(body.tree().get(id)?)?;
+            let adjusted = (score + class_weight(element) as f64) * (1.0 - link_density(element));
+            Some((element, adjusted))
+        })
+        .filter(|(_, score)| *score > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(element, _)| element)
+}
+
+/// The share of an element's text that sits inside links.
+fn link_density(element: ElementRef) -> f64 {
+    let total = visible_text(element).chars().count();
+    if total == 0 {
+        return 0.0;
+    }
+    let linked: usize = element
+        .select(&Selector::parse("a").unwrap())
+        .map(|a| a.text().map(|t| t.chars().count()).sum::<usize>())
+        .sum();
+    linked as f64 / total as f64
+}
+
+fn visible_text(element: ElementRef) -> String {
+    let mut text = String::new();
+    for node in element.descendants() {
+        if let Node::Text(fragment) = node.value()
+            && !node.ancestors().filter_map(ElementRef::wrap).any(is_skipped)
+        {
+            text.push_str(fragment);
+        }
+    }
+    text
+}
+
+fn render_children(node: NodeRef<Node>, out: &mut String, depth: usize) {
+    for child in node.children() {
+        render(child, out, depth);
+    }
+}
+
+/// Append `node` as markdown. `depth` is the list nesting level.
+fn render(node: NodeRef<Node>, out: &mut String, depth: usize) {
+    let Some(element) = ElementRef::wrap(node) else {
+        if let Node::Text(text) = node.value() {
+            out.push_str(&collapse_whitespace(text));
+        }
+        return;
+    };
+    if is_skipped(element) {
+        return;
+    }
+
+    match element.value().name() {
+        name @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6") => {
+            let level = name[1..].parse().unwrap_or(1);
+            let text = inline_text(element);
+            if !text.is_empty() {
+                out.push_str(&format!("\n\n{} {text}\n\n", "#".repeat(level)));
+            }
+        }
+        "pre" => {
+            let code: String = element.text().collect();
+            let language = element
+                .select(&Selector::parse("code").unwrap())
+                .next()
+                .and_then(|c| c.value().classes().find_map(|c| c.strip_prefix("language-")))
+                .unwrap_or_default();
+            out.push_str(&format!("\n\n```{language}\n{}\n```\n\n", code.trim_end()));
+        }
+        "code" => {
+            let code: String = element.text().collect();
+            if !code.trim().is_empty() {
+                out.push_str(&format!("`{}`", code.trim()));
+            }
+        }
+        "ul" | "ol" => {
+            let ordered = element.value().name() == "ol";
+            out.push_str(if depth == 0 { "\n\n" } else { "\n" });
+            let items = element.children().filter_map(ElementRef::wrap).filter(|c| c.value().name() == "li");
+            for (index, item) in items.enumerate() {
+                let marker = if ordered { format!("{}.", index + 1) } else { "-".to_string() };
+                let mut text = String::new();
+                render_children(*item, &mut text, depth + 1);
+                let text = tidy(&text);
+                if !text.is_empty() {
+                    // nested lists come back unindented; indenting the item indents them
+                    out.push_str(&format!("{marker} {}\n", text.replace('\n', "\n  ")));
+                }
+            }
+            if depth == 0 {
+                out.push('\n');
+            }
+        }
+        "strong" | "b" => emphasize(element, out, "**"),
+        "em" | "i" => emphasize(element, out, "*"),
+        "br" => out.push('\n'),
+        "hr" => out.push_str("\n\n---\n\n"),
+        "blockquote" => {
+            let mut text = String::new();
+            render_children(node, &mut text, depth);
+            let quoted: Vec<String> = tidy(&text).lines().map(|l| format!("> {l}")).collect();
+            out.push_str(&format!("\n\n{}\n\n", quoted.join("\n")));
+        }
+        "p" | "div" | "section" | "article" | "main" | "table" | "tr" | "figure" | "dl" | "dd" | "dt" => {
+            out.push_str("\n\n");
+            render_children(node, out, depth);
+            out.push_str("\n\n");
+        }
+        "td" | "th" => {
+            render_children(node, out, depth);
+            out.push(' ');
+        }
+        "img" => {}
+        _ => render_children(node, out, depth),
+    }
+}
+
+fn emphasize(element: ElementRef, out: &mut String, marker: &str) {
+    let text = inline_text(element);
+    if !text.is_empty() {
+        out.push_str(&format!("{marker}{text}{marker}"));
+    }
+}
+
+fn inline_text(element: ElementRef) -> String {
+    collapse_whitespace(&visible_text(element)).trim().to_string()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                collapsed.push(' ');
+            }
+            in_space = true;
+        } else {
+            collapsed.push(c);
+            in_space = false;
+        }
+    }
+    collapsed
+}
+
+/// Trim every line outside code blocks, keeping the indentation of nested
+/// list items, and keep at most one blank line in a row.
+fn tidy(markdown: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut in_code = false;
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        let fence = trimmed.starts_with("```");
+        let line = if in_code && !fence {
+            line
+        } else if is_list_item(trimmed) {
+            line.trim_end()
+        } else {
+            trimmed
+        };
+        if fence {
+            in_code = !in_code;
+        }
+        if line.is_empty() && !in_code && lines.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+fn is_list_item(line: &str) -> bool {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    line.starts_with("- ") || (digits > 0 && line[digits..].starts_with(". "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(name: &str) -> Readable {
+        let path = format!("{}/tests/fixtures/pages/{name}", env!("CARGO_MANIFEST_DIR"));
+        readable(&std::fs::read_to_string(path).unwrap())
+    }
+
+    #[test]
+    fn blog_post_without_the_chrome() {
+        let page = page("blog_post.html");
+        assert_eq!(page.title.as_deref(), Some("Bulk inserts in Postgres from Rust | Rusty Bytes"));
+
+        let text = &page.markdown;
+        assert!(text.starts_with("# Bulk inserts in Postgres from Rust"), "{text}");
+        assert!(text.contains("## Why COPY is faster"), "{text}");
+        assert!(text.contains("- **COPY** streams rows"), "{text}");
+        for boilerplate in ["Accept all cookies", "Subscribe to our newsletter", "Home", "© 2024", "Related posts"] {
+            assert!(!text.contains(boilerplate), "{boilerplate:?} in {text}");
+        }
+    }
+
+    #[test]
+    fn docs_page_keeps_code_blocks_and_lists() {
+        let text = page("docs_page.html").markdown;
+
+        assert!(text.contains("```rust\nlet mut copy = conn\n    .copy_in_raw(\"COPY products FROM STDIN\")\n    .await?;\n```"), "{text}");
+        assert!(text.contains("1. Open a connection from the pool.\n2. Start the `COPY`."), "{text}");
+        assert!(text.contains("- Format: `CSV` or `TEXT`\n  - `TEXT` is the default"), "{text}");
+        assert!(!text.contains("sidebar-toc"), "{text}");
+        assert!(!text.contains("On this page"), "{text}");
+        assert!(!text.contains("window.dataLayer"), "{text}");
+    }
+
+    #[test]
+    fn pages_without_paragraphs_fall_back_to_the_body() {
+        let page = readable("<html><body><nav>Menu</nav><div>Short note.</div></body></html>");
+        assert_eq!(page.title, None);
+        assert_eq!(page.markdown, "Short note.");
+    }
+}
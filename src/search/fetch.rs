@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::stream::{self, StreamExt};
+use reqwest::header::{CONTENT_TYPE, IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use reqwest::{StatusCode, Url};
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+use super::cache::HttpCache;
+use super::document;
+use super::robots::{Robots, Verdict};
+use super::{http_client, user_agent, SearchResult, REQUEST_TIMEOUT};
+use crate::llm::Error;
+use crate::planner::{OnEvent, Page, PlanEvent};
+
+/// How many results per search are read.
+const MAX_PAGES: usize = 5;
+
+/// Bodies larger than this aren't read, let alone cached.
+const MAX_BODY_LEN: usize = 10 * 1024 * 1024;
+
+/// How a search's pages are fetched.
+#[derive(Debug, Clone)]
+pub struct FetchLimits {
+    /// Pages fetched at the same time.
+    pub concurrency: usize,
+    /// Pages fetched at the same time from any one host.
+    pub per_host: usize,
+    /// Time for all of a search's pages; whatever isn't read by then is
+    /// dropped, so one slow site can't hold up the research.
+    pub deadline: Duration,
+    /// Least time between requests to one host; a longer `Crawl-delay` in
+    /// the site's robots.txt wins.
+    pub crawl_delay: Duration,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self { concurrency: MAX_PAGES, per_host: 2, deadline: Duration::from_secs(20), crawl_delay: Duration::from_secs(1) }
+    }
+}
+
+/// A response body and what the server said it is.
+struct Body {
+    content_type: Option<String>,
+    bytes: Vec<u8>,
+}
+
+/// Why a page wasn't read.
+enum Failure {
+    /// robots.txt keeps us out.
+    Skipped(String),
+    /// The body is past [`MAX_BODY_LEN`].
+    TooLarge,
+    /// The site answered with an error page (or anything else but 2xx).
+    Status(StatusCode),
+    Http(reqwest::Error),
+}
+
+impl From<reqwest::Error> for Failure {
+    fn from(e: reqwest::Error) -> Self {
+        Failure::Http(e)
+    }
+}
+
+/// Reads result pages, through the [`HttpCache`] when there is one, minding
+/// each site's robots.txt and pacing requests to each host.
+pub struct Fetcher {
+    client: reqwest::Client,
+    cache: Option<Arc<HttpCache>>,
+    limits: FetchLimits,
+    robots: Robots,
+    /// When each host may next be sent a request.
+    next_request: Mutex<HashMap<String, Instant>>,
+}
+
+impl Fetcher {
+    pub fn new(limits: FetchLimits, cache: Option<HttpCache>) -> Result<Self, Error> {
+        let client = http_client()?;
+        let robots = Robots::new(client.clone(), &user_agent());
+        Ok(Self { client, cache: cache.map(Arc::new), limits, robots, next_request: Mutex::new(HashMap::new()) })
+    }
+
+    /// Default limits, with `CRAWL_DELAY` (seconds) between requests to a
+    /// host, and the cache configured by [`HttpCache::from_env`].
+    pub fn from_env() -> Result<Self, Error> {
+        let mut limits = FetchLimits::default();
+        if let Ok(delay) = std::env::var("CRAWL_DELAY") {
+            let seconds: f64 = delay.trim().parse().map_err(|_| format!("CRAWL_DELAY must be a number of seconds, got '{delay}'"))?;
+            limits.crawl_delay =
+                Duration::try_from_secs_f64(seconds).map_err(|_| format!("CRAWL_DELAY must not be negative, got '{delay}'"))?;
+        }
+        Self::new(limits, HttpCache::from_env()?)
+    }
+
+    pub fn cache(&self) -> Option<&HttpCache> {
+        self.cache.as_deref()
+    }
+
+    /// Run `job` against the cache on the blocking pool: its index is SQLite
+    /// behind a mutex and its bodies are files, neither of which should hold
+    /// up the runtime. `None` without a cache.
+    async fn with_cache<T: Send + 'static>(&self, job: impl FnOnce(&HttpCache) -> T + Send + 'static) -> Option<T> {
+        let cache = self.cache.clone()?;
+        match tokio::task::spawn_blocking(move || job(&cache)).await {
+            Ok(result) => Some(result),
+            Err(e) => {
+                println!("Cache task failed: {e}");
+                None
+            }
+        }
+    }
+
+    /// Read the top results, reporting each page as it's fetched, skipped
+    /// or fails. Results that come with their text are passed through as
+    /// they are; a page that can't be fetched is stood in for by its
+    /// snippet, if it has one. Pages come back in result order, however the fetches finish.
+    pub async fn fetch_pages(&self, results: Vec<SearchResult>, on_event: OnEvent<'_>) -> Vec<Page> {
+        let deadline = Instant::now() + self.limits.deadline;
+
+        let mut hosts: HashMap<String, Arc<Semaphore>> = HashMap::new();
+        let fetches: Vec<_> = results
+            .into_iter()
+            .take(MAX_PAGES)
+            .map(|result| {
+                let slot = hosts
+                    .entry(host(&result.url).unwrap_or_default())
+                    .or_insert_with(|| Arc::new(Semaphore::new(self.limits.per_host.max(1))))
+                    .clone();
+                (result, slot)
+            })
+            .collect();
+
+        stream::iter(fetches)
+            .map(|(result, slot)| self.fetch_page(result, slot, deadline, on_event))
+            .buffered(self.limits.concurrency.max(1))
+            .filter_map(|page| async move { page })
+            .collect()
+            .await
+    }
+
+    async fn fetch_page(
+        &self,
+        result: SearchResult,
+        host_slot: Arc<Semaphore>,
+        deadline: Instant,
+        on_event: OnEvent<'_>,
+    ) -> Option<Page> {
+        let SearchResult { url, title, snippet, content } = result;
+        if let Some(content) = content {
+            on_event(PlanEvent::PageFetched { url: url.clone() });
+            return Some(Page { url, title, content });
+        }
+
+        let fetched = tokio::time::timeout_at(deadline, async {
+            let _permit = host_slot.acquire().await;
+            match self.robots.check(&url).await {
+                Verdict::Allowed { crawl_delay } => self.get(&url, crawl_delay).await,
+                Verdict::Skipped(reason) => Err(Failure::Skipped(reason)),
+            }
+        })
+        .await;
+
+        let error = match fetched {
+            Ok(Ok(Body { content_type, bytes })) => {
+                // PDFs take a while to take apart; keep that off the runtime
+                let read = tokio::task::spawn_blocking(move || document::read(content_type.as_deref(), &bytes)).await;
+                match read.unwrap_or_else(|e| Err(e.to_string())) {
+                    Ok(page) => {
+                        on_event(PlanEvent::PageFetched { url: url.clone() });
+                        return Some(Page { url, title: title.or(page.title), content: page.markdown });
+                    }
+                    Err(reason) => {
+                        on_event(PlanEvent::PageFailed { url: url.clone(), error: reason.clone() });
+                        let content = match snippet {
+                            Some(snippet) => format!("(not read: {reason}; search snippet only) {snippet}"),
+                            None => format!("(not read: {reason})"),
+                        };
+                        return Some(Page { url, title, content });
+                    }
+                }
+            }
+            Ok(Err(Failure::Skipped(reason))) => {
+                on_event(PlanEvent::PageSkipped { url: url.clone(), reason });
+                return snippet.map(|snippet| Page { url, title, content: format!("(search snippet only) {snippet}") });
+            }
+            Ok(Err(Failure::TooLarge)) => format!("larger than {} MiB, not read", MAX_BODY_LEN / (1024 * 1024)),
+            Ok(Err(Failure::Status(status))) => format!("the site answered {status}"),
+            Ok(Err(Failure::Http(e))) if e.is_timeout() => format!("timed out after {REQUEST_TIMEOUT:?}"),
+            Ok(Err(Failure::Http(e))) => e.to_string(),
+            Err(_) => format!("timed out: the search's {:?} deadline passed", self.limits.deadline),
+        };
+        on_event(PlanEvent::PageFailed { url: url.clone(), error });
+        snippet.map(|snippet| Page { url, title, content: format!("(search snippet only) {snippet}") })
+    }
+
+    /// The body of `url`: from the cache while it's fresh, revalidated with
+    /// `If-None-Match` / `If-Modified-Since` once it's stale. Only requests
+    /// that reach the site wait for its crawl delay.
+    ///
+    /// Bodies are read up to [`MAX_BODY_LEN`]. Images, audio, video and
+    /// fonts aren't downloaded at all; anything else is read far enough to
+    /// tell a mislabelled PDF from a real binary, and only the first bytes
+    /// of a binary are kept. Only documents are cached.
+    async fn get(&self, url: &str, crawl_delay: Option<Duration>) -> Result<Body, Failure> {
+        let key = url.to_string();
+        let cached = match self.with_cache(move |cache| cache.lookup(&key)).await.flatten() {
+            Some(page) if page.fresh => return Ok(Body { content_type: page.content_type, bytes: page.body }),
+            cached => cached,
+        };
+
+        let mut request = self.client.get(url);
+        if let Some(page) = &cached {
+            if let Some(etag) = &page.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &page.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        self.wait_turn(url, crawl_delay).await;
+        let mut response = request.send().await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+
+        if status == StatusCode::NOT_MODIFIED
+            && let Some(page) = cached
+        {
+            let (key, fresh_headers) = (url.to_string(), headers.clone());
+            if let Some(Err(e)) = self.with_cache(move |cache| cache.revalidated(&key, &fresh_headers)).await {
+                println!("Cannot update the cache entry for {url}: {e}");
+            }
+            return Ok(Body { content_type: page.content_type, bytes: page.body });
+        }
+        if !status.is_success() {
+            // An error page says nothing about what the result was about
+            return Err(Failure::Status(status));
+        }
+
+        let content_type = headers.get(CONTENT_TYPE).and_then(|t| t.to_str().ok()).map(str::to_string);
+        if document::is_media(content_type.as_deref()) {
+            // Described from its type alone; no need to download it
+            return Ok(Body { content_type, bytes: Vec::new() });
+        }
+        if response.content_length().is_some_and(|len| len > MAX_BODY_LEN as u64) {
+            return Err(Failure::TooLarge);
+        }
+        let mut bytes = Vec::new();
+        let mut sniffed = false;
+        while let Some(chunk) = response.chunk().await? {
+            if bytes.len() + chunk.len() > MAX_BODY_LEN {
+                return Err(Failure::TooLarge);
+            }
+            bytes.extend_from_slice(&chunk);
+            if !sniffed && bytes.len() >= document::SNIFF_LEN {
+                sniffed = true;
+                if document::is_binary(content_type.as_deref(), &bytes) {
+                    // Enough to say what it is; the rest would go unread
+                    return Ok(Body { content_type, bytes });
+                }
+            }
+        }
+
+        if status == StatusCode::OK && self.cache.is_some() && !document::is_binary(content_type.as_deref(), &bytes) {
+            let (key, body) = (url.to_string(), bytes.clone());
+            if let Some(Err(e)) = self.with_cache(move |cache| cache.store(&key, &headers, &body)).await {
+                println!("Cannot cache {url}: {e}");
+            }
+        }
+        Ok(Body { content_type, bytes })
+    }
+
+    /// Wait until `url`'s host may be sent another request, booking the
+    /// slot after ours for whoever asks next.
+    async fn wait_turn(&self, url: &str, crawl_delay: Option<Duration>) {
+        let Some(host) = host(url) else {
+            return;
+        };
+        let delay = crawl_delay.unwrap_or_default().max(self.limits.crawl_delay);
+        let start = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let slot = next_request.entry(host).or_insert_with(Instant::now);
+            let start = (*slot).max(Instant::now());
+            *slot = start + delay;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+    }
+}
+
+fn host(url: &str) -> Option<String> {
+    Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_lowercase))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    struct TestServer {
+        port: u16,
+        /// Requests in flight at the peak.
+        peak: Arc<AtomicUsize>,
+        /// Every request as `<path> <status>`.
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    /// Serves `/slow/*` after 200ms, never answers `/hang`, serves `/fresh`
+    /// as cacheable for an hour and `/etag` as always revalidated, `/missing`
+    /// as a 404 error page, `*.png` as an image, `*.pdf` and `*.zip` as
+    /// downloads of a PDF and an archive, and `robots` (if any) as its
+    /// robots.txt.
+    async fn test_server(robots: Option<&'static str>) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let (server_peak, server_log) = (peak.clone(), log.clone());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (active, peak, log) = (active.clone(), server_peak.clone(), server_log.clone());
+                tokio::spawn(async move {
+                    let mut request = vec![0; 2048];
+                    let read = socket.read(&mut request).await.unwrap();
+                    let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+                    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    if path == "/hang" {
+                        futures::future::pending::<()>().await;
+                    }
+                    if path.starts_with("/slow") {
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                    }
+                    active.fetch_sub(1, Ordering::SeqCst);
+
+                    let (status, headers) = match path.as_str() {
+                        "/robots.txt" if robots.is_none() => ("404 Not Found", ""),
+                        "/etag" if request.contains("if-none-match: \"v1\"") => ("304 Not Modified", "ETag: \"v1\"\r\n"),
+                        "/etag" => ("200 OK", "ETag: \"v1\"\r\nCache-Control: no-cache\r\n"),
+                        "/fresh" => ("200 OK", "Cache-Control: max-age=3600\r\n"),
+                        "/missing" => ("404 Not Found", ""),
+                        _ => ("200 OK", ""),
+                    };
+                    log.lock().unwrap().push(format!("{path} {}", &status[..3]));
+                    let body = if status.starts_with("304") {
+                        Vec::new()
+                    } else if path == "/robots.txt" {
+                        robots.unwrap_or_default().into()
+                    } else if path.ends_with(".pdf") {
+                        std::fs::read(format!("{}/tests/fixtures/corpus/copy-benchmarks.pdf", env!("CARGO_MANIFEST_DIR"))).unwrap()
+                    } else if path.ends_with(".zip") {
+                        [b"PK\x03\x04".as_slice(), &[0; 4096]].concat()
+                    } else {
+                        format!("<html><body><p>Page {path}</p></body></html>").into()
+                    };
+                    let content_type = match path.rsplit('.').next() {
+                        Some("png") => "image/png",
+                        Some("pdf") | Some("zip") => "application/x-download",
+                        _ => "text/html",
+                    };
+                    let head = format!(
+                        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    );
+                    socket.write_all(head.as_bytes()).await.unwrap();
+                    socket.write_all(&body).await.unwrap();
+                });
+            }
+        });
+        TestServer { port, peak, log }
+    }
+
+    fn result(url: String) -> SearchResult {
+        SearchResult { url, title: None, snippet: Some("snippet".to_string()), content: None }
+    }
+
+    fn contents(pages: &[Page]) -> Vec<&str> {
+        pages.iter().map(|p| p.content.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn pages_are_fetched_concurrently_within_per_host_caps() {
+        let server = test_server(None).await;
+        let port = server.port;
+        let results = vec![
+            result(format!("http://127.0.0.1:{port}/slow/1")),
+            result(format!("http://127.0.0.1:{port}/slow/2")),
+            result(format!("http://127.0.0.1:{port}/slow/3")),
+            result(format!("http://localhost:{port}/slow/4")),
+        ];
+        let limits = FetchLimits { concurrency: 4, per_host: 2, deadline: Duration::from_secs(10), crawl_delay: Duration::ZERO };
+        let fetcher = Fetcher::new(limits, None).unwrap();
+
+        let started = std::time::Instant::now();
+        let pages = fetcher.fetch_pages(results, &|_| {}).await;
+
+        assert_eq!(contents(&pages), ["Page /slow/1", "Page /slow/2", "Page /slow/3", "Page /slow/4"]);
+        // two from 127.0.0.1 plus the one from localhost, then the third from 127.0.0.1
+        assert_eq!(server.peak.load(Ordering::SeqCst), 3);
+        assert!(started.elapsed() < Duration::from_millis(700), "{:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn fetches_past_the_deadline_are_reported_as_timed_out() {
+        let port = test_server(None).await.port;
+        let results = vec![result(format!("http://127.0.0.1:{port}/hang")), result(format!("http://127.0.0.1:{port}/slow/1"))];
+        let limits = FetchLimits { concurrency: 2, per_host: 2, deadline: Duration::from_millis(500), crawl_delay: Duration::ZERO };
+        let fetcher = Fetcher::new(limits, None).unwrap();
+
+        let events = std::sync::Mutex::new(Vec::new());
+        let record = |event: PlanEvent| events.lock().unwrap().push(event);
+        let pages = fetcher.fetch_pages(results, &record).await;
+
+        assert_eq!(contents(&pages), ["(search snippet only) snippet", "Page /slow/1"]);
+        let failures: Vec<String> = events
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .filter_map(|e| match e {
+                PlanEvent::PageFailed { url, error } => Some(format!("{url}: {error}")),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(failures, [format!("http://127.0.0.1:{port}/hang: timed out: the search's 500ms deadline passed")]);
+    }
+
+    #[tokio::test]
+    async fn cached_pages_are_reused_and_revalidated() {
+        let server = test_server(None).await;
+        let port = server.port;
+        let dir = std::env::temp_dir().join(format!("mls-gigachad-fetch-cache-{}", uuid::Uuid::new_v4()));
+        let cache = HttpCache::open(&dir, Duration::from_secs(3600), 1024 * 1024).unwrap();
+        let limits = FetchLimits { crawl_delay: Duration::ZERO, ..FetchLimits::default() };
+        let fetcher = Fetcher::new(limits, Some(cache)).unwrap();
+        let results = || vec![result(format!("http://127.0.0.1:{port}/fresh")), result(format!("http://127.0.0.1:{port}/etag"))];
+
+        let first = fetcher.fetch_pages(results(), &|_| {}).await;
+        let second = fetcher.fetch_pages(results(), &|_| {}).await;
+
+        assert_eq!(contents(&first), ["Page /fresh", "Page /etag"]);
+        assert_eq!(contents(&second), contents(&first));
+        let mut log = server.log.lock().unwrap().clone();
+        log.sort();
+        assert_eq!(log, ["/etag 200", "/etag 304", "/fresh 200", "/robots.txt 404"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn binaries_are_described_without_being_downloaded_or_cached() {
+        let port = test_server(None).await.port;
+        let dir = std::env::temp_dir().join(format!("mls-gigachad-fetch-binary-{}", uuid::Uuid::new_v4()));
+        let cache = HttpCache::open(&dir, Duration::from_secs(3600), 1024 * 1024).unwrap();
+        let limits = FetchLimits { crawl_delay: Duration::ZERO, ..FetchLimits::default() };
+        let fetcher = Fetcher::new(limits, Some(cache)).unwrap();
+
+        let pages = fetcher.fetch_pages(vec![result(format!("http://127.0.0.1:{port}/logo.png"))], &|_| {}).await;
+
+        assert_eq!(contents(&pages), ["(not read: an image (image/png) can't be read as text; search snippet only) snippet"]);
+        assert!(fetcher.cache().unwrap().entries().unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn error_pages_fall_back_to_the_snippet() {
+        let port = test_server(None).await.port;
+        let limits = FetchLimits { crawl_delay: Duration::ZERO, ..FetchLimits::default() };
+        let fetcher = Fetcher::new(limits, None).unwrap();
+
+        let events = std::sync::Mutex::new(Vec::new());
+        let record = |event: PlanEvent| events.lock().unwrap().push(event);
+        let pages = fetcher.fetch_pages(vec![result(format!("http://127.0.0.1:{port}/missing"))], &record).await;
+
+        assert_eq!(contents(&pages), ["(search snippet only) snippet"]);
+        assert!(matches!(
+            &events.into_inner().unwrap()[..],
+            [PlanEvent::PageFailed { error, .. }] if error == "the site answered 404 Not Found"
+        ));
+    }
+
+    #[tokio::test]
+    async fn downloads_are_sniffed_for_pdfs() {
+        let port = test_server(None).await.port;
+        let limits = FetchLimits { crawl_delay: Duration::ZERO, ..FetchLimits::default() };
+        let fetcher = Fetcher::new(limits, None).unwrap();
+        let results = vec![
+            result(format!("http://127.0.0.1:{port}/benchmarks.pdf")),
+            result(format!("http://127.0.0.1:{port}/source.zip")),
+        ];
+
+        let pages = fetcher.fetch_pages(results, &|_| {}).await;
+
+        assert!(pages[0].content.contains("4.1 seconds"), "{}", pages[0].content);
+        assert_eq!(
+            pages[1].content,
+            "(not read: a binary file (application/x-download) can't be read as text; search snippet only) snippet"
+        );
+    }
+
+    #[tokio::test]
+    async fn robots_txt_is_obeyed_and_its_crawl_delay_kept() {
+        let server = test_server(Some("User-agent: *\nDisallow: /slow/private\nCrawl-delay: 0.3\n")).await;
+        let port = server.port;
+        let results = vec![
+            result(format!("http://127.0.0.1:{port}/slow/1")),
+            result(format!("http://127.0.0.1:{port}/slow/private")),
+            result(format!("http://127.0.0.1:{port}/slow/2")),
+        ];
+        let limits = FetchLimits { concurrency: 3, per_host: 3, deadline: Duration::from_secs(10), crawl_delay: Duration::ZERO };
+        let fetcher = Fetcher::new(limits, None).unwrap();
+
+        let events = std::sync::Mutex::new(Vec::new());
+        let record = |event: PlanEvent| events.lock().unwrap().push(event);
+        let started = std::time::Instant::now();
+        let pages = fetcher.fetch_pages(results, &record).await;
+
+        assert_eq!(contents(&pages), ["Page /slow/1", "(search snippet only) snippet", "Page /slow/2"]);
+        let skipped: Vec<String> = events
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .filter_map(|e| match e {
+                PlanEvent::PageSkipped { url, reason } => Some(format!("{url}: {reason}")),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(skipped, [format!("http://127.0.0.1:{port}/slow/private: disallowed by robots.txt")]);
+
+        // robots.txt is read once, the private page never, and the second
+        // page waits out the crawl delay after the first
+        let mut log = server.log.lock().unwrap().clone();
+        log.sort();
+        assert_eq!(log, ["/robots.txt 200", "/slow/1 200", "/slow/2 200"]);
+        assert_eq!(server.peak.load(Ordering::SeqCst), 1);
+        assert!(started.elapsed() >= Duration::from_millis(500), "{:?}", started.elapsed());
+    }
+}
@@ -0,0 +1,288 @@
+//! Search over a local directory of documents instead of the web, for
+//! air-gapped machines and for knowledge no search engine can see (internal
+//! design docs, vendored crate docs).
+//!
+//! Documents are split into passages and kept in an SQLite FTS5 index on
+//! disk, which is brought up to date with the directory at startup: only new
+//! and modified files are read again. The index is only a cache; deleting it
+//! rebuilds it from the directory.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::document::read;
+use super::rank::chunks;
+use super::{SearchProvider, SearchResult};
+use crate::llm::{required_env, Error};
+
+const DEFAULT_LOCAL_INDEX: &str = "corpus.db";
+
+/// Passages returned per query.
+const MAX_RESULTS: usize = 5;
+
+/// Passages are cut at paragraph breaks once they reach this many bytes.
+const PASSAGE_LEN: usize = 1500;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS documents (
+        path     TEXT PRIMARY KEY,
+        modified INTEGER NOT NULL,
+        size     INTEGER NOT NULL
+    );
+    CREATE VIRTUAL TABLE IF NOT EXISTS passages USING fts5(
+        path UNINDEXED,
+        title,
+        body,
+        tokenize = 'porter unicode61'
+    );
+";
+
+pub struct LocalCorpus {
+    root: PathBuf,
+    /// Shared with the blocking tasks that run the searches.
+    conn: Arc<Mutex<Connection>>,
+}
+
+/// What [`LocalCorpus::refresh`] did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RefreshStats {
+    pub indexed: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+impl LocalCorpus {
+    /// `LOCAL_CORPUS_DIR` is the directory to search; `LOCAL_INDEX` names the
+    /// index file, `corpus.db` by default.
+    pub fn from_env() -> Result<Self, Error> {
+        let root = required_env("LOCAL_CORPUS_DIR")?;
+        let index = std::env::var("LOCAL_INDEX").unwrap_or_else(|_| DEFAULT_LOCAL_INDEX.to_string());
+
+        let corpus = Self::open(root, index)?;
+        let stats = corpus.refresh()?;
+        println!(
+            "Local corpus {}: {} indexed, {} unchanged, {} removed",
+            corpus.root.display(),
+            stats.indexed,
+            stats.unchanged,
+            stats.removed
+        );
+        Ok(corpus)
+    }
+
+    /// Open (or create) the index for `root` at `index`; `:memory:` keeps it in RAM.
+    pub fn open(root: impl AsRef<Path>, index: impl AsRef<Path>) -> Result<Self, Error> {
+        let root = root.as_ref();
+        let root = root
+            .canonicalize()
+            .map_err(|e| format!("cannot read corpus directory {}: {e}", root.display()))?;
+        let index = index.as_ref();
+        let conn = Connection::open(index)
+            .map_err(|e| format!("cannot open corpus index {}: {e}", index.display()))?;
+        conn.execute_batch(SCHEMA)?;
+
+        Ok(Self { root, conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    /// Index new and modified documents and drop deleted ones.
+    pub fn refresh(&self) -> Result<RefreshStats, Error> {
+        let mut files = Vec::new();
+        collect_documents(&self.root, &mut files)?;
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut stats = RefreshStats::default();
+
+        for file in &files {
+            let metadata = std::fs::metadata(file)?;
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64);
+            let size = metadata.len() as i64;
+            let path = file.to_string_lossy();
+
+            let indexed: Option<(i64, i64)> = tx
+                .query_row("SELECT modified, size FROM documents WHERE path = ?1", [&path], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .optional()?;
+            if indexed == Some((modified, size)) {
+                stats.unchanged += 1;
+                continue;
+            }
+
+            tx.execute("DELETE FROM passages WHERE path = ?1", [&path])?;
+            match extract(file) {
+                Ok((title, text)) => {
+                    for passage in chunks(&text, PASSAGE_LEN) {
+                        tx.execute(
+                            "INSERT INTO passages (path, title, body) VALUES (?1, ?2, ?3)",
+                            params![path, title, passage],
+                        )?;
+                    }
+                }
+                // Recorded anyway, so a broken file isn't retried on every start
+                Err(e) => println!("Skipping {}: {e}", file.display()),
+            }
+            tx.execute(
+                "INSERT OR REPLACE INTO documents (path, modified, size) VALUES (?1, ?2, ?3)",
+                params![path, modified, size],
+            )?;
+            stats.indexed += 1;
+        }
+
+        let known: Vec<String> = {
+            let mut statement = tx.prepare("SELECT path FROM documents")?;
+            statement.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?
+        };
+        for path in known {
+            if !files.iter().any(|f| f.to_string_lossy() == path) {
+                tx.execute("DELETE FROM passages WHERE path = ?1", [&path])?;
+                tx.execute("DELETE FROM documents WHERE path = ?1", [&path])?;
+                stats.removed += 1;
+            }
+        }
+
+        tx.commit()?;
+        Ok(stats)
+    }
+}
+
+/// The best passages for `query`. Blocks on SQLite, so [`LocalCorpus::search`]
+/// runs it on the blocking pool.
+fn lookup(conn: &Mutex<Connection>, query: &str) -> Result<Vec<SearchResult>, Error> {
+    let Some(query) = match_expression(query) else {
+        return Ok(Vec::new());
+    };
+    let conn = conn.lock().unwrap();
+    // Title matches count double; `path` is unindexed and gets no weight
+    let mut statement = conn.prepare(
+        "SELECT path, title, body FROM passages WHERE passages MATCH ?1
+         ORDER BY bm25(passages, 0.0, 2.0, 1.0) LIMIT ?2",
+    )?;
+    let results = statement
+        .query_map(params![query, MAX_RESULTS], |row| {
+            Ok(SearchResult {
+                url: format!("file://{}", row.get::<_, String>(0)?),
+                title: Some(row.get(1)?),
+                snippet: None,
+                content: Some(row.get(2)?),
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(results)
+}
+
+#[async_trait]
+impl SearchProvider for LocalCorpus {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Error> {
+        let (conn, query) = (Arc::clone(&self.conn), query.to_string());
+        tokio::task::spawn_blocking(move || lookup(&conn, &query)).await?
+    }
+}
+
+fn is_document(path: &Path) -> bool {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    matches!(extension.as_str(), "md" | "markdown" | "txt" | "html" | "htm" | "pdf")
+}
+
+/// Every document under `dir`, skipping hidden files and directories.
+fn collect_documents(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')) {
+            continue;
+        }
+        if path.is_dir() {
+            collect_documents(&path, files)?;
+        } else if is_document(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The document's title and plain text.
+fn extract(path: &Path) -> Result<(String, String), Error> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    let content_type = match extension.as_str() {
+        "pdf" => "application/pdf",
+        "html" | "htm" => "text/html",
+        "md" | "markdown" => "text/markdown",
+        _ => "text/plain",
+    };
+    let page = read(Some(content_type), &std::fs::read(path)?)?;
+    Ok((page.title.unwrap_or(file_name), page.markdown))
+}
+
+/// The query's words as an FTS5 expression matching any of them, quoted so
+/// that `-`, `:` or `"` in the model's query aren't read as FTS syntax.
+fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("\"{w}\""))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" OR "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus() -> LocalCorpus {
+        let corpus = LocalCorpus::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/corpus"), ":memory:").unwrap();
+        corpus.refresh().unwrap();
+        corpus
+    }
+
+    #[tokio::test]
+    async fn every_document_type_is_searchable() {
+        let corpus = corpus();
+
+        let results = corpus.search("copy from stdin benchmark").await.unwrap();
+        assert!(results[0].url.ends_with("/copy-benchmarks.pdf"), "{results:?}");
+        assert!(results[0].content.as_deref().unwrap().contains("4.1 seconds"));
+
+        let results = corpus.search("politeness: user-agent?").await.unwrap();
+        assert_eq!(results[0].title.as_deref(), Some("Product scraper design"));
+
+        let results = corpus.search("semaphore").await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title.as_deref(), Some("Tokio tutorial: spawning"));
+        assert!(!results[0].content.as_deref().unwrap().contains("not content"));
+
+        assert!(corpus.search("kubernetes").await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn refresh_only_reads_changed_files() {
+        let dir = std::env::temp_dir().join(format!("mls-gigachad-corpus-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::write(dir.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+        std::fs::write(dir.join("a.md"), "# A\n\nalpha").unwrap();
+        std::fs::write(dir.join("b.txt"), "beta").unwrap();
+        std::fs::write(dir.join("logo.png"), [0x89, b'P', b'N', b'G']).unwrap();
+
+        let corpus = LocalCorpus::open(&dir, ":memory:").unwrap();
+        assert_eq!(corpus.refresh().unwrap(), RefreshStats { indexed: 2, unchanged: 0, removed: 0 });
+
+        std::fs::write(dir.join("a.md"), "# A\n\nalpha and gamma").unwrap();
+        std::fs::remove_file(dir.join("b.txt")).unwrap();
+        assert_eq!(corpus.refresh().unwrap(), RefreshStats { indexed: 1, unchanged: 0, removed: 1 });
+        assert_eq!(lookup(&corpus.conn, "gamma").unwrap().len(), 1);
+        assert!(lookup(&corpus.conn, "beta").unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,289 @@
+//! Keep only the parts of a search's pages that answer the query.
+//!
+//! Pages are split into chunks, the chunks are ranked against the query with
+//! BM25 (computed locally, over just this search's chunks) and the best ones
+//! are kept until the token budget is spent. What the model gets back from one
+//! `search_web` call is therefore bounded, however long the pages are.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::llm::estimate_tokens;
+use crate::planner::Page;
+
+const DEFAULT_TOKENS: usize = 3000;
+const DEFAULT_CHUNKS: usize = 8;
+
+/// Chunks are cut at paragraph breaks once they reach this many bytes.
+const CHUNK_LEN: usize = 1200;
+
+/// BM25 term frequency saturation and length normalisation.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// How much of a search's pages reach the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    /// Estimated tokens across all kept chunks.
+    pub tokens: usize,
+    /// Most chunks kept.
+    pub chunks: usize,
+}
+
+impl Budget {
+    /// `SEARCH_RESULT_TOKENS` (default 3000) and `SEARCH_RESULT_CHUNKS`
+    /// (default 8); unset or unparsable values fall back to the defaults.
+    pub fn from_env() -> Self {
+        let read = |key: &str, default: usize| {
+            std::env::var(key).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(default)
+        };
+        Self { tokens: read("SEARCH_RESULT_TOKENS", DEFAULT_TOKENS), chunks: read("SEARCH_RESULT_CHUNKS", DEFAULT_CHUNKS) }
+    }
+}
+
+/// The pages cut down to their best chunks for `query`, in page order and
+/// with each page's chunks in reading order. Pages with nothing kept are
+/// dropped.
+pub fn best_chunks(query: &str, pages: &[Page], budget: Budget) -> Vec<Page> {
+    let chunked: Vec<(usize, String)> = pages
+        .iter()
+        .enumerate()
+        .flat_map(|(page, p)| chunks(&p.content, CHUNK_LEN).into_iter().map(move |c| (page, c)))
+        .collect();
+    let texts: Vec<&str> = chunked.iter().map(|(_, c)| c.as_str()).collect();
+    let scores = bm25(query, &texts);
+
+    // Best first; if nothing matches at all, reading order
+    let mut order: Vec<usize> = (0..chunked.len()).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]).then(a.cmp(&b)));
+
+    let mut kept = HashSet::new();
+    let mut tokens = 0;
+    for index in order {
+        if kept.len() == budget.chunks {
+            break;
+        }
+        let cost = estimate_tokens(&chunked[index].1);
+        if tokens + cost > budget.tokens {
+            continue; // a smaller chunk further down may still fit
+        }
+        tokens += cost;
+        kept.insert(index);
+    }
+
+    let mut selected = Vec::new();
+    for (page_index, page) in pages.iter().enumerate() {
+        let parts: Vec<&str> = chunked
+            .iter()
+            .enumerate()
+            .filter(|(i, (p, _))| *p == page_index && kept.contains(i))
+            .map(|(_, (_, chunk))| chunk.as_str())
+            .collect();
+        if !parts.is_empty() {
+            selected.push(Page { content: parts.join("\n\n[...]\n\n"), ..page.clone() });
+        }
+    }
+    selected
+}
+
+/// Blocks packed into chunks of about `max_len` bytes, whitespace and line
+/// breaks kept so code and lists arrive as they were written. A block longer
+/// than that is cut between lines, and a line longer than that between
+/// words.
+pub fn chunks(text: &str, max_len: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut flush = |current: &mut String| {
+        let chunk = std::mem::take(current);
+        let chunk = chunk.trim_end();
+        if !chunk.is_empty() {
+            chunks.push(chunk.to_string());
+        }
+    };
+
+    for block in blocks(text) {
+        if block.len() < max_len {
+            if !current.is_empty() && current.len() + 2 + block.len() >= max_len {
+                flush(&mut current);
+            }
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(&block);
+        } else {
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            for piece in pieces(&block, max_len) {
+                if !current.trim().is_empty() && current.len() + piece.len() >= max_len {
+                    flush(&mut current);
+                }
+                current.push_str(piece);
+            }
+        }
+        if current.len() >= max_len / 2 {
+            flush(&mut current);
+        }
+    }
+    flush(&mut current);
+    chunks
+}
+
+/// Paragraphs, lists and code blocks: the text between blank lines, except
+/// that a fenced code block is never broken up.
+fn blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut block: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        if line.trim().is_empty() && !in_fence {
+            if !block.is_empty() {
+                blocks.push(block.join("\n"));
+                block.clear();
+            }
+            continue;
+        }
+        block.push(line);
+    }
+    if !block.is_empty() {
+        blocks.push(block.join("\n"));
+    }
+    blocks
+}
+
+/// `block` as whole lines, with only the lines longer than `max_len` cut
+/// between words; every piece keeps the whitespace that follows it.
+fn pieces(block: &str, max_len: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    for line in block.split_inclusive('\n') {
+        if line.len() < max_len {
+            pieces.push(line);
+        } else {
+            pieces.extend(line.split_inclusive(char::is_whitespace));
+        }
+    }
+    pieces
+}
+
+fn terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|t| !t.is_empty()).map(str::to_lowercase).collect()
+}
+
+/// Okapi BM25 score of each document for `query`.
+fn bm25(query: &str, documents: &[&str]) -> Vec<f64> {
+    let documents: Vec<Vec<String>> = documents.iter().map(|d| terms(d)).collect();
+    let count = documents.len() as f64;
+    let average_len = documents.iter().map(Vec::len).sum::<usize>() as f64 / count.max(1.0);
+
+    let mut query_terms = terms(query);
+    query_terms.sort();
+    query_terms.dedup();
+
+    let mut containing: HashMap<&str, usize> = HashMap::new();
+    for document in &documents {
+        let unique: HashSet<&str> = document.iter().map(String::as_str).collect();
+        for term in &query_terms {
+            if unique.contains(term.as_str()) {
+                *containing.entry(term).or_default() += 1;
+            }
+        }
+    }
+
+    documents
+        .iter()
+        .map(|document| {
+            let len = document.len() as f64;
+            query_terms
+                .iter()
+                .map(|term| {
+                    let frequency = document.iter().filter(|t| *t == term).count() as f64;
+                    if frequency == 0.0 {
+                        return 0.0;
+                    }
+                    let n = containing.get(term.as_str()).copied().unwrap_or(0) as f64;
+                    let idf = ((count - n + 0.5) / (n + 0.5) + 1.0).ln();
+                    idf * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * len / average_len.max(1.0)))
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(url: &str, content: &str) -> Page {
+        Page { url: url.to_string(), title: None, content: content.to_string() }
+    }
+
+    fn filler(topic: &str) -> String {
+        format!("{topic} ").repeat(250)
+    }
+
+    #[test]
+    fn matching_chunks_win_and_keep_their_page_order() {
+        let pages = [
+            page("https://a.example", &format!("{}\n\n{}", filler("cookies"), "sqlx copy_in_raw streams rows with COPY.")),
+            page("https://b.example", &filler("weather")),
+            page("https://c.example", "Batched INSERT is slower than COPY for bulk loads."),
+        ];
+        let selected = best_chunks("sqlx COPY bulk", &pages, Budget { tokens: 3000, chunks: 2 });
+
+        let kept: Vec<(&str, &str)> = selected.iter().map(|p| (p.url.as_str(), p.content.as_str())).collect();
+        assert_eq!(
+            kept,
+            [
+                ("https://a.example", "sqlx copy_in_raw streams rows with COPY."),
+                ("https://c.example", "Batched INSERT is slower than COPY for bulk loads."),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_token_budget_is_never_exceeded() {
+        let pages = [page("https://a.example", &format!("{}\n\n{}", filler("tokio"), filler("tokio runtime")))];
+        let budget = Budget { tokens: 400, chunks: 10 };
+        let selected = best_chunks("tokio", &pages, budget);
+
+        assert_eq!(selected.len(), 1);
+        let kept: Vec<&str> = selected[0].content.split("\n\n[...]\n\n").collect();
+        assert!(kept.iter().map(|c| estimate_tokens(c)).sum::<usize>() <= budget.tokens);
+        assert!(kept.len() < chunks(&pages[0].content, CHUNK_LEN).len(), "everything was kept");
+    }
+
+    #[test]
+    fn unmatched_queries_keep_the_opening_chunks() {
+        let pages = [page("https://a.example", &format!("Intro.\n\n{}", filler("body")))];
+        let selected = best_chunks("kubernetes", &pages, Budget { tokens: 3000, chunks: 1 });
+        assert!(selected[0].content.starts_with("Intro.\n\nbody body"), "{}", selected[0].content);
+    }
+
+    #[test]
+    fn code_blocks_and_lists_keep_their_lines() {
+        let code = "```rust\nfn main() {\n    let rows = 1;\n\n    println!(\"{rows}\");\n}\n```";
+        let list = "- COPY\n  - binary format\n- INSERT";
+        let text = format!("{}\n\n{code}\n\n{list}", filler("intro"));
+
+        let chunks = chunks(&text, 1200);
+        assert!(chunks.iter().any(|c| c.contains(code)), "{chunks:#?}");
+        assert!(chunks.iter().any(|c| c.contains(list)), "{chunks:#?}");
+    }
+
+    #[test]
+    fn words_longer_than_a_chunk_make_no_empty_chunks() {
+        let text = format!("{} tail", "x".repeat(50));
+        assert_eq!(chunks(&text, 10), ["x".repeat(50), "tail".to_string()]);
+    }
+
+    #[test]
+    fn long_paragraphs_are_split_between_words() {
+        let text = format!("intro\n\n{}", "word ".repeat(700));
+        let chunks = chunks(&text, 1500);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.len() < 1500));
+        assert!(chunks[0].starts_with("intro\n\nword word"));
+    }
+}
@@ -0,0 +1,317 @@
+//! robots.txt checks for result pages (RFC 9309).
+//!
+//! Each site's robots.txt is fetched once and kept in memory for a day. A
+//! site whose robots.txt is missing may be read in full; one whose robots.txt
+//! can't be fetched (a server error, a timeout, a 429) is not read at all, as
+//! the RFC asks, until it is tried again a few minutes later.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::{StatusCode, Url};
+use tokio::sync::OnceCell;
+use tokio::time::Instant;
+
+/// How long a site's robots.txt is trusted before it's fetched again.
+const ROBOTS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a site whose robots.txt couldn't be fetched is left alone before
+/// it's asked again.
+const UNREACHABLE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Anything past this much of a robots.txt is ignored.
+const MAX_ROBOTS_LEN: usize = 500 * 1024;
+
+/// Whether a page may be fetched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// Fetch it, waiting this long between requests to the site if set.
+    Allowed { crawl_delay: Option<Duration> },
+    /// Leave it alone, for this reason.
+    Skipped(String),
+}
+
+#[derive(Debug)]
+enum Policy {
+    Rules(Rules),
+    Unreachable(String),
+}
+
+impl Policy {
+    fn ttl(&self) -> Duration {
+        match self {
+            Policy::Rules(_) => ROBOTS_TTL,
+            Policy::Unreachable(_) => UNREACHABLE_TTL,
+        }
+    }
+}
+
+/// A site's robots.txt and when it was fetched, filled in by whichever
+/// check needs it first while the others wait.
+type Site = Arc<OnceCell<(Policy, Instant)>>;
+
+/// Remembers each site's robots.txt.
+pub struct Robots {
+    client: reqwest::Client,
+    /// Our product token, matched against `User-agent` lines.
+    agent: String,
+    sites: Mutex<HashMap<String, Site>>,
+}
+
+impl Robots {
+    /// `user_agent` is what the client sends; its first word (up to the `/`)
+    /// picks the robots.txt group that applies to us.
+    pub fn new(client: reqwest::Client, user_agent: &str) -> Self {
+        let agent = user_agent.split(['/', ' ']).next().unwrap_or_default().to_lowercase();
+        Self { client, agent, sites: Mutex::new(HashMap::new()) }
+    }
+
+    pub async fn check(&self, url: &str) -> Verdict {
+        let Ok(url) = Url::parse(url) else {
+            return Verdict::Skipped("not a valid URL".to_string());
+        };
+        if !matches!(url.scheme(), "http" | "https") {
+            return Verdict::Allowed { crawl_delay: None };
+        }
+        let origin = url.origin().ascii_serialization();
+
+        let site = {
+            let mut sites = self.sites.lock().unwrap();
+            let site = sites.entry(origin.clone()).or_default();
+            if site.get().is_some_and(|(policy, fetched)| fetched.elapsed() > policy.ttl()) {
+                *site = Arc::default();
+            }
+            site.clone()
+        };
+        let (policy, _) = site.get_or_init(|| async { (self.fetch(&origin).await, Instant::now()) }).await;
+
+        match policy {
+            Policy::Unreachable(error) => Verdict::Skipped(format!("robots.txt unreachable: {error}")),
+            Policy::Rules(rules) => {
+                let mut path = url.path().to_string();
+                if let Some(query) = url.query() {
+                    path = format!("{path}?{query}");
+                }
+                if rules.allows(&path) {
+                    Verdict::Allowed { crawl_delay: rules.crawl_delay }
+                } else {
+                    Verdict::Skipped("disallowed by robots.txt".to_string())
+                }
+            }
+        }
+    }
+
+    async fn fetch(&self, origin: &str) -> Policy {
+        let response = match self.client.get(format!("{origin}/robots.txt")).send().await {
+            Ok(response) => response,
+            Err(e) => return Policy::Unreachable(e.to_string()),
+        };
+        let status = response.status();
+        if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+            // No robots.txt (or none for us to see): everything is allowed
+            return Policy::Rules(Rules::default());
+        }
+        if !status.is_success() {
+            return Policy::Unreachable(format!("the site answered {status}"));
+        }
+        match response.bytes().await {
+            Ok(body) => {
+                let body = String::from_utf8_lossy(&body[..body.len().min(MAX_ROBOTS_LEN)]).into_owned();
+                Policy::Rules(Rules::parse(&body, &self.agent))
+            }
+            Err(e) => Policy::Unreachable(e.to_string()),
+        }
+    }
+}
+
+/// The rules of the groups that apply to one user agent.
+#[derive(Debug, Default, PartialEq)]
+struct Rules {
+    /// `(allow, pattern)` pairs.
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Rules {
+    /// The groups naming `agent`, or the `*` groups if none do.
+    fn parse(robots: &str, agent: &str) -> Self {
+        #[derive(Default)]
+        struct Group {
+            agents: Vec<String>,
+            rules: Rules,
+        }
+
+        let mut groups: Vec<Group> = Vec::new();
+        let mut in_rules = true;
+        for line in robots.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            if key == "user-agent" {
+                if in_rules {
+                    groups.push(Group::default());
+                    in_rules = false;
+                }
+                if let Some(group) = groups.last_mut() {
+                    group.agents.push(value.to_lowercase());
+                }
+                continue;
+            }
+            let Some(group) = groups.last_mut() else {
+                continue; // rules before any user-agent line
+            };
+            in_rules = true;
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => group.rules.rules.push((key == "allow", value.to_string())),
+                "crawl-delay" => {
+                    group.rules.crawl_delay = value.parse::<f64>().ok().and_then(|s| Duration::try_from_secs_f64(s).ok())
+                }
+                _ => {}
+            }
+        }
+
+        let names_us = |g: &Group| g.agents.iter().any(|a| a == agent);
+        let named = groups.iter().any(names_us);
+        let applies = |g: &Group| if named { names_us(g) } else { g.agents.iter().any(|a| a == "*") };
+
+        let mut rules = Rules::default();
+        for group in groups.into_iter().filter(applies) {
+            rules.rules.extend(group.rules.rules);
+            rules.crawl_delay = rules.crawl_delay.max(group.rules.crawl_delay);
+        }
+        rules
+    }
+
+    /// The longest matching rule decides; `Allow` wins a tie, and a path no
+    /// rule matches is allowed.
+    fn allows(&self, path: &str) -> bool {
+        if path == "/robots.txt" {
+            return true;
+        }
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+}
+
+/// Whether `pattern` (with `*` for any run of characters and a trailing `$`
+/// for the end of the path) matches the start of `path`.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        if last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const ROBOTS: &str = "\
+# Example robots.txt
+User-agent: *
+Disallow: /private
+Allow: /private/press
+Disallow: /*.pdf$
+
+User-agent: BadBot
+User-agent: mls-gigachad
+Disallow: /search
+Crawl-delay: 2.5
+
+User-agent: OtherBot
+Disallow: /
+";
+
+    #[test]
+    fn our_group_wins_over_the_wildcard() {
+        let ours = Rules::parse(ROBOTS, "mls-gigachad");
+        assert_eq!(ours.crawl_delay, Some(Duration::from_millis(2500)));
+        assert!(!ours.allows("/search?q=rust"));
+        assert!(ours.allows("/private/anything"), "the * group doesn't apply to us");
+
+        let anyone = Rules::parse(ROBOTS, "curl");
+        assert_eq!(anyone.crawl_delay, None);
+        assert!(anyone.allows("/search"));
+        assert!(!anyone.allows("/private/keys"));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_robots_txt_skips_the_site_until_it_is_asked_again() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let origin = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for answer in ["429 Too Many Requests", "200 OK"] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let _ = socket.read(&mut [0; 2048]).await.unwrap();
+                let body = "User-agent: *\nDisallow: /private\n";
+                let response = format!("HTTP/1.1 {answer}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        let robots = Robots::new(reqwest::Client::new(), "mls-gigachad/0.1");
+
+        let skipped = robots.check(&format!("{origin}/docs")).await;
+        assert_eq!(skipped, Verdict::Skipped("robots.txt unreachable: the site answered 429 Too Many Requests".to_string()));
+
+        // Once the short wait is over the site is asked again
+        let asked = Instant::now().checked_sub(UNREACHABLE_TTL + Duration::from_secs(1)).unwrap();
+        let site = OnceCell::new_with(Some((Policy::Unreachable("429".to_string()), asked)));
+        robots.sites.lock().unwrap().insert(origin.clone(), Arc::new(site));
+        assert_eq!(robots.check(&format!("{origin}/docs")).await, Verdict::Allowed { crawl_delay: None });
+        assert!(matches!(robots.check(&format!("{origin}/private")).await, Verdict::Skipped(_)));
+    }
+
+    #[test]
+    fn groups_must_name_our_whole_token() {
+        let robots = "User-agent: mls\nDisallow: /\n\nUser-agent: chad\nDisallow: /\n\nUser-agent: *\nDisallow: /private\n";
+        let rules = Rules::parse(robots, "mls-gigachad");
+        assert!(rules.allows("/docs"), "a group for a prefix of our name doesn't apply to us");
+        assert!(!rules.allows("/private"));
+    }
+
+    #[test]
+    fn the_longest_rule_decides() {
+        let rules = Rules::parse(ROBOTS, "curl");
+        assert!(rules.allows("/private/press/2024"));
+        assert!(!rules.allows("/private"));
+        assert!(!rules.allows("/docs/manual.pdf"));
+        assert!(rules.allows("/docs/manual.pdf.html"));
+        assert!(rules.allows("/robots.txt"));
+        assert!(Rules::default().allows("/anything"));
+    }
+
+    #[test]
+    fn wildcards_match_any_run_of_characters() {
+        assert!(matches("/fish*.php", "/fish/salmon.php?id=1"));
+        assert!(matches("/*/print", "/docs/print/page"));
+        assert!(!matches("/*/print$", "/docs/print/page"));
+        assert!(matches("/*.php$", "/index.php"));
+        assert!(!matches("/*.php$", "/index.php5"));
+        assert!(!matches("/private", "/public"));
+    }
+}
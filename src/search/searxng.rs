@@ -0,0 +1,77 @@
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::{http_client, SearchProvider, SearchResult};
+use crate::llm::{required_env, Error};
+
+/// A SearXNG instance's JSON API. The instance must list `json` under
+/// `search.formats` in its `settings.yml`.
+pub struct SearXng {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl SearXng {
+    /// `SEARXNG_URL`, e.g. `http://localhost:8888`.
+    pub fn from_env() -> Result<Self, Error> {
+        let base_url = required_env("SEARXNG_URL")?;
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: http_client()?,
+        })
+    }
+}
+
+#[async_trait]
+impl SearchProvider for SearXng {
+    fn name(&self) -> &str {
+        "searxng"
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Error> {
+        let url = format!("{}/search", self.base_url);
+        let response = self.client.get(&url).query(&[("q", query), ("format", "json")]).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(format!("searxng request failed with {status}: {error_text}").into());
+        }
+        Ok(parse(&response.json().await?))
+    }
+}
+
+fn parse(body: &Value) -> Vec<SearchResult> {
+    let Some(results) = body["results"].as_array() else {
+        return Vec::new();
+    };
+    results
+        .iter()
+        .filter_map(|result| {
+            Some(SearchResult {
+                url: result["url"].as_str()?.to_string(),
+                title: result["title"].as_str().map(str::to_string),
+                snippet: result["content"].as_str().map(str::to_string).filter(|s| !s.is_empty()),
+                content: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_searxng_results() {
+        let body = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/searxng_results.json"))
+            .unwrap();
+        let results = parse(&serde_json::from_str(&body).unwrap());
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].url, "https://docs.rs/sqlx/latest/sqlx/postgres/struct.PgCopyIn.html");
+        assert_eq!(results[0].title.as_deref(), Some("PgCopyIn in sqlx::postgres - Rust"));
+        assert_eq!(results[2].title, None);
+        assert_eq!(results[2].snippet.as_deref(), Some("UNNEST lets you bind one array per column."));
+    }
+}
@@ -0,0 +1,402 @@
+//! The plan as JSON: the types behind `static/plan.schema.json`, the checks
+//! the schema alone can't express, and the write-validate-retry loop that
+//! produces it.
+
+use std::collections::{HashMap, HashSet};
+
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::llm::{Error, LlmProvider, Message};
+
+/// The published JSON Schema, also served at `/plan.schema.json`.
+pub const SCHEMA: &str = include_str!("../static/plan.schema.json");
+
+/// How many replies the model gets to produce a valid plan.
+const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct StructuredPlan {
+    pub title: String,
+    pub summary: String,
+    pub goals: Vec<String>,
+    pub assumptions: Vec<String>,
+    pub milestones: Vec<Milestone>,
+    pub tasks: Vec<Task>,
+    pub risks: Vec<Risk>,
+    pub open_questions: Vec<String>,
+    pub sources: Vec<Source>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct Milestone {
+    pub id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// When it should be reached, in the model's words ("end of week 2").
+    pub target: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct Task {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    /// Id of the milestone the task belongs to.
+    pub milestone: String,
+    pub estimate: Estimate,
+    /// Ids of the tasks that must finish first.
+    pub depends_on: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct Estimate {
+    pub value: f64,
+    pub unit: EstimateUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum EstimateUnit {
+    Hours,
+    Days,
+    Weeks,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct Risk {
+    pub description: String,
+    pub likelihood: Level,
+    pub impact: Level,
+    pub mitigation: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum Level {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct Source {
+    /// The `[n]` the plan cites it by, when it came from the research.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub url: String,
+}
+
+impl StructuredPlan {
+    /// Parse a model reply, tolerating a surrounding code fence, and check it.
+    pub fn parse(reply: &str) -> Result<Self, Vec<String>> {
+        let plan: Self = serde_json::from_str(strip_code_fence(reply)).map_err(|e| vec![e.to_string()])?;
+        let problems = plan.problems();
+        if problems.is_empty() { Ok(plan) } else { Err(problems) }
+    }
+
+    /// Everything wrong with the plan that deserializing doesn't catch:
+    /// empty sections, duplicate ids, dangling references and cycles.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.title.trim().is_empty() {
+            problems.push("title is empty".to_string());
+        }
+        for (field, empty) in [
+            ("goals", self.goals.is_empty()),
+            ("milestones", self.milestones.is_empty()),
+            ("tasks", self.tasks.is_empty()),
+        ] {
+            if empty {
+                problems.push(format!("{field} must not be empty"));
+            }
+        }
+
+        let mut milestone_ids = HashSet::new();
+        for milestone in &self.milestones {
+            if !milestone_ids.insert(milestone.id.as_str()) {
+                problems.push(format!("duplicate milestone id {:?}", milestone.id));
+            }
+        }
+
+        let mut task_ids = HashSet::new();
+        for task in &self.tasks {
+            if !task_ids.insert(task.id.as_str()) {
+                problems.push(format!("duplicate task id {:?}", task.id));
+            }
+        }
+        for task in &self.tasks {
+            if !milestone_ids.contains(task.milestone.as_str()) {
+                problems.push(format!("task {:?} names unknown milestone {:?}", task.id, task.milestone));
+            }
+            if task.estimate.value <= 0.0 {
+                problems.push(format!("task {:?} needs a positive estimate", task.id));
+            }
+            for dependency in &task.depends_on {
+                if dependency == &task.id {
+                    problems.push(format!("task {:?} depends on itself", task.id));
+                } else if !task_ids.contains(dependency.as_str()) {
+                    problems.push(format!("task {:?} depends on unknown task {dependency:?}", task.id));
+                }
+            }
+        }
+        if let Some(task) = self.first_task_in_cycle() {
+            problems.push(format!("task {task:?} is part of a dependency cycle"));
+        }
+
+        for source in &self.sources {
+            if !["http://", "https://", "file://"].iter().any(|scheme| source.url.starts_with(scheme)) {
+                problems.push(format!("source url {:?} is not an http(s) or file URL", source.url));
+            }
+        }
+        problems
+    }
+
+    /// Kahn's algorithm: whatever can't be scheduled is on (or behind) a
+    /// cycle. Unknown and self dependencies are reported separately.
+    fn first_task_in_cycle(&self) -> Option<&str> {
+        let known: HashSet<&str> = self.tasks.iter().map(|t| t.id.as_str()).collect();
+        let mut waiting_on: HashMap<&str, HashSet<&str>> = self
+            .tasks
+            .iter()
+            .map(|t| {
+                let dependencies = t
+                    .depends_on
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|d| *d != t.id && known.contains(d))
+                    .collect();
+                (t.id.as_str(), dependencies)
+            })
+            .collect();
+
+        let mut ready: Vec<&str> = waiting_on.iter().filter(|(_, deps)| deps.is_empty()).map(|(id, _)| *id).collect();
+        while let Some(done) = ready.pop() {
+            waiting_on.remove(done);
+            for (id, dependencies) in waiting_on.iter_mut() {
+                if dependencies.remove(done) && dependencies.is_empty() {
+                    ready.push(id);
+                }
+            }
+        }
+
+        self.tasks.iter().map(|t| t.id.as_str()).find(|id| waiting_on.contains_key(id))
+    }
+}
+
+/// Models like to wrap JSON in ```json fences despite being told not to.
+fn strip_code_fence(reply: &str) -> &str {
+    let reply = reply.trim();
+    let Some(rest) = reply.strip_prefix("```") else {
+        return reply;
+    };
+    let body = rest.split_once('\n').map_or("", |(_, body)| body);
+    body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+}
+
+/// Have the model turn the knowledge base into a [`StructuredPlan`], feeding
+/// validation errors back until it gets it right or runs out of attempts.
+pub async fn write(llm: &dyn LlmProvider, knowledge_base: &str) -> Result<StructuredPlan, Error> {
+    let mut messages = vec![
+        Message::new(
+            "system",
+            format!(
+                "You are **PlanBot-final**. Using the knowledge base below, write a comprehensive \
+                technical plan as a single JSON document matching this JSON Schema. Every task \
+                needs an estimate and the ids of the tasks it depends on. Reply with the JSON \
+                only: no prose, no code fences.\n\n{SCHEMA}"
+            ),
+        ),
+        Message::new("user", knowledge_base),
+    ];
+
+    let mut problems = Vec::new();
+    for attempt in 1..=MAX_ATTEMPTS {
+        let reply = llm.stream(messages.clone(), None, &|_| {}).await?;
+        let truncated = reply.truncated();
+        let text = reply.content.unwrap_or_default();
+
+        problems = match StructuredPlan::parse(&text) {
+            Ok(plan) => return Ok(plan),
+            Err(problems) => problems,
+        };
+        if truncated {
+            problems.push("the reply was cut off at the output token limit; be more concise".to_string());
+        }
+        println!("Structured plan attempt {attempt} was invalid: {}", problems.join("; "));
+
+        messages.push(Message::new("assistant", text));
+        messages.push(Message::new(
+            "user",
+            format!(
+                "That plan is invalid:\n- {}\nReply with the corrected JSON document only.",
+                problems.join("\n- ")
+            ),
+        ));
+    }
+
+    Err(format!("no valid plan after {MAX_ATTEMPTS} attempts: {}", problems.join("; ")).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::MockLlm;
+
+    fn fixture(name: &str) -> String {
+        std::fs::read_to_string(format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))).unwrap()
+    }
+
+    #[test]
+    fn schema_and_types_agree() {
+        let schema: serde_json::Value = serde_json::from_str(SCHEMA).unwrap();
+        let mut plan = StructuredPlan::parse(&fixture("structured_plan.json")).unwrap();
+        // Every optional field filled in, so the schema sees all of them
+        plan.milestones[0].description = Some("Scaffolding".to_string());
+        plan.tasks[0].depends_on = vec!["T2".to_string()];
+        plan.sources = vec![Source { number: Some(1), title: Some("sqlx docs".to_string()), url: "https://docs.rs/sqlx".to_string() }];
+        let plan = serde_json::to_value(&plan).unwrap();
+
+        let mut disagreements = Vec::new();
+        agree(&schema, &plan, "", &mut disagreements);
+        assert!(disagreements.is_empty(), "{disagreements:#?}");
+
+        let variants = |values: &[serde_json::Value]| values.iter().map(|v| v.as_str().unwrap().to_string()).collect::<Vec<_>>();
+        let levels = &schema["properties"]["risks"]["items"]["properties"]["likelihood"]["enum"];
+        assert_eq!(variants(levels.as_array().unwrap()), [Level::Low, Level::Medium, Level::High].map(|l| to_name(&l)));
+        let units = &schema["properties"]["tasks"]["items"]["properties"]["estimate"]["properties"]["unit"]["enum"];
+        assert_eq!(
+            variants(units.as_array().unwrap()),
+            [EstimateUnit::Hours, EstimateUnit::Days, EstimateUnit::Weeks].map(|u| to_name(&u))
+        );
+    }
+
+    fn to_name<T: Serialize>(value: &T) -> String {
+        serde_json::to_value(value).unwrap().as_str().unwrap().to_string()
+    }
+
+    /// Whether `plan` with the value at `pointer` changed by `edit` still
+    /// deserializes.
+    fn accepts(plan: &serde_json::Value, pointer: &str, edit: impl FnOnce(&mut serde_json::Value)) -> bool {
+        let mut plan = plan.clone();
+        edit(plan.pointer_mut(pointer).unwrap());
+        serde_json::from_value::<StructuredPlan>(plan).is_ok()
+    }
+
+    /// Compare the schema at `node` with the serialized plan at `pointer`:
+    /// property names, which of them are required, unknown fields, types and
+    /// enum values.
+    fn agree(node: &serde_json::Value, plan: &serde_json::Value, pointer: &str, disagreements: &mut Vec<String>) {
+        let value = plan.pointer(pointer).unwrap();
+        let at = if pointer.is_empty() { "/" } else { pointer };
+        if let Some(allowed) = node["enum"].as_array() {
+            for name in allowed {
+                if !accepts(plan, pointer, |v| *v = name.clone()) {
+                    disagreements.push(format!("{at}: the schema allows {name} but the type doesn't"));
+                }
+            }
+            if accepts(plan, pointer, |v| *v = serde_json::json!("not-a-variant")) {
+                disagreements.push(format!("{at}: the type takes values the schema's enum doesn't"));
+            }
+            return;
+        }
+        let matches = match node["type"].as_str().unwrap_or_default() {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.is_u64() || value.is_i64(),
+            other => {
+                disagreements.push(format!("{at}: the schema's type {other:?} isn't checked here"));
+                return;
+            }
+        };
+        if !matches {
+            disagreements.push(format!("{at}: serialized as {value}, the schema says {}", node["type"]));
+            return;
+        }
+
+        if let Some(items) = node.get("items") {
+            match value.as_array().unwrap().is_empty() {
+                true => disagreements.push(format!("{at}: empty, so its items can't be compared")),
+                false => agree(items, plan, &format!("{pointer}/0"), disagreements),
+            }
+        }
+        let Some(properties) = node["properties"].as_object() else {
+            return;
+        };
+        let mut in_schema: Vec<&String> = properties.keys().collect();
+        let mut in_type: Vec<&String> = value.as_object().unwrap().keys().collect();
+        in_schema.sort();
+        in_type.sort();
+        if in_schema != in_type {
+            disagreements.push(format!("{at}: the schema has {in_schema:?}, the type {in_type:?}"));
+        }
+        let required: Vec<&str> = node["required"].as_array().into_iter().flatten().filter_map(|r| r.as_str()).collect();
+        for (name, property) in properties {
+            let optional = accepts(plan, pointer, |v| {
+                v.as_object_mut().unwrap().remove(name);
+            });
+            if optional == required.contains(&name.as_str()) {
+                let (schema, rust) = if optional { ("required", "optional") } else { ("optional", "required") };
+                disagreements.push(format!("{at}/{name}: {schema} in the schema, {rust} in the type"));
+            }
+            if value.get(name).is_some() {
+                agree(property, plan, &format!("{pointer}/{name}"), disagreements);
+            }
+        }
+        let closed = node["additionalProperties"] == serde_json::json!(false);
+        let open = accepts(plan, pointer, |v| {
+            v.as_object_mut().unwrap().insert("unexpected".to_string(), serde_json::json!(1));
+        });
+        if closed == open {
+            disagreements.push(format!("{at}: unknown fields are handled differently by the schema and the type"));
+        }
+    }
+
+    #[test]
+    fn fenced_replies_are_accepted() {
+        let reply = format!("```json\n{}\n```", fixture("structured_plan.json"));
+        let plan = StructuredPlan::parse(&reply).unwrap();
+        assert_eq!(plan.tasks.len(), 3);
+        assert_eq!(plan.tasks[2].depends_on, ["T1", "T2"]);
+    }
+
+    #[test]
+    fn broken_references_and_cycles_are_reported() {
+        let mut plan = StructuredPlan::parse(&fixture("structured_plan.json")).unwrap();
+        plan.tasks[0].depends_on = vec!["T3".to_string()];
+        plan.tasks[1].milestone = "M9".to_string();
+        plan.tasks[1].depends_on = vec!["T7".to_string()];
+
+        let problems = plan.problems();
+        assert!(problems.contains(&r#"task "T2" names unknown milestone "M9""#.to_string()), "{problems:?}");
+        assert!(problems.contains(&r#"task "T2" depends on unknown task "T7""#.to_string()), "{problems:?}");
+        let cycles: Vec<_> = problems.iter().filter(|p| p.ends_with("is part of a dependency cycle")).collect();
+        assert_eq!(cycles, [r#"task "T1" is part of a dependency cycle"#], "{problems:?}");
+    }
+
+    #[tokio::test]
+    async fn invalid_replies_are_retried_with_the_errors() {
+        let llm = MockLlm::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/structured_plan_retry.json"))
+            .unwrap();
+
+        let plan = write(&llm, "## Overall synthesis\nreqwest + scraper, sqlx into Postgres").await.unwrap();
+        assert_eq!(plan.title, "Nightly product scraper");
+
+        let requests = llm.requests();
+        assert_eq!(requests.len(), 3);
+        let first_feedback = requests[1].last().unwrap().content.as_deref().unwrap();
+        assert!(first_feedback.starts_with("That plan is invalid:"), "{first_feedback}");
+        let second_feedback = requests[2].last().unwrap().content.as_deref().unwrap();
+        assert!(second_feedback.contains(r#"depends on unknown task "T9""#), "{second_feedback}");
+    }
+}